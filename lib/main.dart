@@ -42,7 +42,11 @@ class _MyHomePageState extends State<MyHomePage> {
   final int imageHeight = 1000;
   bool makePlot = false;
   bool plotted = false;
-  late Uint8List bmp;
+  late ui.Image image;
+  MutexRenderer? renderer;
+  // Drag distance in image pixels not yet applied to the renderer
+  Offset pendingPan = Offset.zero;
+  bool panning = false;
   final functionController = TextEditingController();
 
   @override
@@ -50,6 +54,65 @@ class _MyHomePageState extends State<MyHomePage> {
     super.initState();
   }
 
+  /// Renders the function to raw pixels and uploads them as an image, without
+  /// encoding and decoding an image file. The renderer is kept for panning.
+  Future<ui.Image> renderPlot() async {
+    pendingPan = Offset.zero;
+    renderer = await api.rendererNew(
+      width: imageWidth,
+      height: imageHeight,
+      funStr: functionController.text,
+      options: const DCOptions(
+        viewport: Viewport(xmin: -5, xmax: 5, ymin: -5, ymax: 5),
+        preserveAspect: true,
+        scheme: Scheme.hpluv,
+        modulusMap: ModulusMap.rational(),
+        phaseContours: 0,
+        lineWidth: 1,
+        params: [],
+        derivative: 0,
+        supersampling: 1,
+        jitter: false,
+        adaptive: false,
+        zeroColor: Rgb(r: 0, g: 0, b: 0),
+        infinityColor: Rgb(r: 255, g: 255, b: 255),
+        nanColor: Rgb(r: 128, g: 128, b: 128),
+      ),
+    );
+    return imageFromPixels(await api.rendererPixels(renderer: renderer!));
+  }
+
+  Future<ui.Image> imageFromPixels(Uint8List pixels) {
+    final completer = Completer<ui.Image>();
+    ui.decodeImageFromPixels(pixels, imageWidth, imageHeight,
+        ui.PixelFormat.rgba8888, completer.complete);
+    return completer.future;
+  }
+
+  /// Pans the plot by `delta` image pixels. Only one pan runs at a time,
+  /// drags made meanwhile are applied together once it finishes.
+  Future<void> panPlot(Offset delta) async {
+    pendingPan += delta;
+    if (panning || renderer == null) {
+      return;
+    }
+    panning = true;
+    while (true) {
+      final dx = pendingPan.dx.round();
+      final dy = pendingPan.dy.round();
+      if (dx == 0 && dy == 0) {
+        break;
+      }
+      pendingPan -= Offset(dx.toDouble(), dy.toDouble());
+      final pixels = await api.rendererPan(renderer: renderer!, dx: dx, dy: dy);
+      final panned = await imageFromPixels(pixels);
+      setState(() {
+        image = panned;
+      });
+    }
+    panning = false;
+  }
+
   @override
   void dispose() {
     // Clean up the controller when the widget is disposed.
@@ -73,30 +136,25 @@ class _MyHomePageState extends State<MyHomePage> {
       if (!plotted) {
         print("plotting...");
         plot = FutureBuilder(
-            future: api.colorBmp(
-              width: imageWidth,
-              height: imageHeight,
-              funStr: functionController.text,
-              options: const DCOptions(xmin: -5, xmax: 5, ymin: -5, ymax: 5),
-            ),
-            builder: (BuildContext context, AsyncSnapshot<Uint8List> snapshot) {
+            future: renderPlot(),
+            builder: (BuildContext context, AsyncSnapshot<ui.Image> snapshot) {
               if (snapshot.hasData) {
                 plotted = true;
-                bmp = snapshot.data as Uint8List;
-                return Image.memory(
-                  bmp,
+                image = snapshot.data as ui.Image;
+                return RawImage(
+                  image: image,
                   fit: BoxFit.contain,
                   alignment: Alignment.topLeft,
                 );
               } else if (snapshot.hasError) {
-                return const Text("Error!");
+                return Text("Error: ${snapshot.error}");
               } else {
                 return const Text("Plotting...");
               }
             });
       } else {
-        plot = Image.memory(
-          bmp,
+        plot = RawImage(
+          image: image,
           fit: BoxFit.contain,
           alignment: Alignment.topLeft,
         );
@@ -119,7 +177,19 @@ class _MyHomePageState extends State<MyHomePage> {
           mainAxisAlignment: MainAxisAlignment.start,
           crossAxisAlignment: CrossAxisAlignment.center,
           children: [
-            AspectRatio(aspectRatio: 1.0, child: plot),
+            AspectRatio(
+              aspectRatio: 1.0,
+              child: LayoutBuilder(
+                builder: (BuildContext context, BoxConstraints constraints) {
+                  // Convert drags from logical pixels to image pixels
+                  final scale = imageWidth / constraints.maxWidth;
+                  return GestureDetector(
+                    onPanUpdate: (details) => panPlot(details.delta * scale),
+                    child: plot,
+                  );
+                },
+              ),
+            ),
             Flexible(
               fit: FlexFit.loose,
               flex: 1,
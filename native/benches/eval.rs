@@ -0,0 +1,48 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use native::parser;
+use num::Complex;
+
+/// An expression of about 20 nodes
+const EXPR: &str = "(z^3 - 2z + 1) / (sin(z) + cos(z) * exp(z / 2)) + sqrt(z) i";
+const SIDE: usize = 256;
+
+fn grid() -> Vec<Complex<f64>> {
+    let step = 4.0 / SIDE as f64;
+    (0..SIDE * SIDE)
+        .map(|i| {
+            Complex::new(
+                -2.0 + (i % SIDE) as f64 * step,
+                -2.0 + (i / SIDE) as f64 * step,
+            )
+        })
+        .collect()
+}
+
+fn eval_benchmark(c: &mut Criterion) {
+    let points = grid();
+    let mut group = c.benchmark_group("eval");
+
+    let closure = parser::parse_to_fn(EXPR).unwrap();
+    group.bench_function("closure", |b| {
+        b.iter(|| {
+            points.iter().fold(Complex::new(0.0, 0.0), |acc, &z| {
+                acc + closure(black_box(z))
+            })
+        })
+    });
+
+    let program = parser::parse_to_program(EXPR).unwrap();
+    let mut regs = Vec::with_capacity(program.len());
+    group.bench_function("bytecode", |b| {
+        b.iter(|| {
+            points.iter().fold(Complex::new(0.0, 0.0), |acc, &z| {
+                acc + program.eval_with(black_box(z), &[], &mut regs)
+            })
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, eval_benchmark);
+criterion_main!(benches);
@@ -2,7 +2,18 @@
 // When adding new code to your project, note that only items used
 // here will be transformed to their Dart equivalents.
 
+use anyhow::Result;
+use flutter_rust_bridge::{RustOpaque, StreamSink, ZeroCopyBuffer};
+pub use std::sync::Mutex;
+
 use crate::domain_color;
+use crate::job;
+pub use crate::job::RenderJob;
+use crate::parser;
+pub use crate::renderer::Renderer;
+use crate::tiles;
+pub use crate::tiles::TileRenderer;
+use crate::viewport::Viewport;
 
 // The convention for Rust identifiers is the snake_case,
 // and they are automatically converted to camelCase on the Dart side.
@@ -16,6 +27,227 @@ pub fn color_bmp(
     height: usize,
     fun_str: String,
     options: domain_color::DCOptions,
-) -> Vec<u8> {
-    domain_color::color_bmp(width, height, &fun_str, options)
+) -> Result<Vec<u8>> {
+    Ok(domain_color::color_bmp(width, height, &fun_str, options)?)
+}
+
+/// Renders the function as raw RGBA8 pixels, rows from the top with a stride of
+/// `4 * width` bytes, ready for `decodeImageFromPixels` with
+/// `PixelFormat.rgba8888`
+pub fn color_rgba(
+    width: usize,
+    height: usize,
+    fun_str: String,
+    options: domain_color::DCOptions,
+) -> Result<ZeroCopyBuffer<Vec<u8>>> {
+    let image = domain_color::render(width, height, &fun_str, &options)?;
+    Ok(ZeroCopyBuffer(image.pixels))
+}
+
+/// Starts rendering the function in the background. Poll the job with
+/// `render_job_progress` and `render_job_result`, and cancel it with
+/// `render_job_cancel` once the render is no longer needed.
+pub fn render_job_start(
+    width: usize,
+    height: usize,
+    fun_str: String,
+    options: domain_color::DCOptions,
+) -> RustOpaque<RenderJob> {
+    RustOpaque::new(job::start(width, height, fun_str, options))
+}
+
+/// A job that is not running yet, to pass to `color_progressive`
+pub fn render_job_new() -> RustOpaque<RenderJob> {
+    RustOpaque::new(RenderJob::new())
+}
+
+/// Fraction of the job's pixels colored so far, from 0 to 1
+pub fn render_job_progress(job: RustOpaque<RenderJob>) -> f64 {
+    job.progress()
+}
+
+/// Stops the job's render as soon as possible
+pub fn render_job_cancel(job: RustOpaque<RenderJob>) {
+    job.cancel()
+}
+
+/// The raw RGBA8 pixels rendered by a job from `render_job_start`, like
+/// `color_rgba`, or `None` while it is still running. The pixels can only be
+/// taken once. Fails if the render failed or was cancelled.
+pub fn render_job_result(job: RustOpaque<RenderJob>) -> Result<Option<ZeroCopyBuffer<Vec<u8>>>> {
+    match job.take_result() {
+        Some(result) => Ok(Some(ZeroCopyBuffer(result?.pixels))),
+        None => Ok(None),
+    }
+}
+
+/// A pass of a progressive render, as raw RGBA8 pixels
+pub struct RenderFrame {
+    pub width: usize,
+    pub height: usize,
+    /// The frame is this many times smaller than the full image
+    pub scale: usize,
+    pub pixels: ZeroCopyBuffer<Vec<u8>>,
+}
+
+/// Renders the function at 1/8, 1/4, 1/2 and then full resolution, adding a
+/// frame to `sink` after each pass. Cancelling `job` stops the render, and
+/// its progress covers all the passes.
+pub fn color_progressive(
+    width: usize,
+    height: usize,
+    fun_str: String,
+    options: domain_color::DCOptions,
+    job: RustOpaque<RenderJob>,
+    sink: StreamSink<RenderFrame>,
+) -> Result<()> {
+    let result = job::render_progressive(width, height, &fun_str, &options, &job, |frame| {
+        sink.add(RenderFrame {
+            width: frame.image.width,
+            height: frame.image.height,
+            scale: frame.scale,
+            pixels: ZeroCopyBuffer(frame.image.pixels),
+        });
+    });
+    sink.close();
+    Ok(result?)
+}
+
+/// Renders the function and keeps the image, so it can be panned with
+/// `renderer_pan` without recomputing the pixels still in view
+pub fn renderer_new(
+    width: usize,
+    height: usize,
+    fun_str: String,
+    options: domain_color::DCOptions,
+) -> Result<RustOpaque<Mutex<Renderer>>> {
+    let renderer = Renderer::new(width, height, &fun_str, &options)?;
+    Ok(RustOpaque::new(Mutex::new(renderer)))
+}
+
+/// The renderer's current image, as raw RGBA8 pixels like `color_rgba`
+pub fn renderer_pixels(renderer: RustOpaque<Mutex<Renderer>>) -> ZeroCopyBuffer<Vec<u8>> {
+    let renderer = renderer.lock().unwrap();
+    ZeroCopyBuffer(renderer.image().pixels.clone())
+}
+
+/// The viewport shown in the renderer's current image
+pub fn renderer_viewport(renderer: RustOpaque<Mutex<Renderer>>) -> Viewport {
+    renderer.lock().unwrap().viewport()
+}
+
+/// Moves the plot by whole pixels, `dx` to the right and `dy` down, as when
+/// dragging it, and returns the new pixels
+pub fn renderer_pan(
+    renderer: RustOpaque<Mutex<Renderer>>,
+    dx: i64,
+    dy: i64,
+) -> ZeroCopyBuffer<Vec<u8>> {
+    let mut renderer = renderer.lock().unwrap();
+    ZeroCopyBuffer(renderer.pan(dx, dy).pixels.clone())
+}
+
+/// Zooms in by `factor` about the pixel coordinates `(x, y)` and returns the
+/// new pixels
+pub fn renderer_zoom(
+    renderer: RustOpaque<Mutex<Renderer>>,
+    factor: f64,
+    x: f64,
+    y: f64,
+) -> Result<ZeroCopyBuffer<Vec<u8>>> {
+    let mut renderer = renderer.lock().unwrap();
+    Ok(ZeroCopyBuffer(renderer.zoom(factor, x, y)?.pixels.clone()))
+}
+
+/// A tile renderer whose cache holds at most `budget_bytes` of pixels, each
+/// tile taking `4 * 256 * 256` bytes
+pub fn tile_renderer_new(budget_bytes: usize) -> RustOpaque<Mutex<TileRenderer>> {
+    RustOpaque::new(Mutex::new(TileRenderer::new(budget_bytes)))
+}
+
+/// A 256 by 256 tile of the plot, as raw RGBA8 pixels like `color_rgba`
+pub struct TileData {
+    pub zoom: i32,
+    pub x: i64,
+    pub y: i64,
+    /// The square of the plane covered by the tile
+    pub viewport: Viewport,
+    pub pixels: ZeroCopyBuffer<Vec<u8>>,
+}
+
+/// The tiles covering the viewport of `options` when shown at `width` by
+/// `height` pixels, at the lowest zoom level at least as sharp as that.
+/// Tiles already rendered for the same function and options come from the
+/// cache.
+pub fn tile_renderer_tiles(
+    renderer: RustOpaque<Mutex<TileRenderer>>,
+    fun_str: String,
+    options: domain_color::DCOptions,
+    width: usize,
+    height: usize,
+) -> Result<Vec<TileData>> {
+    domain_color::validate(width, height, &options)?;
+    let viewport = options.effective_viewport(width, height);
+    let zoom = tiles::zoom_for(&viewport, width, height);
+    let tiles = renderer
+        .lock()
+        .unwrap()
+        .tiles(&fun_str, &options, &viewport, zoom)?;
+    Ok(tiles
+        .into_iter()
+        .map(|tile| TileData {
+            zoom: tile.key.zoom,
+            x: tile.key.x,
+            y: tile.key.y,
+            viewport: tile.viewport,
+            pixels: ZeroCopyBuffer(tile.image.pixels.clone()),
+        })
+        .collect())
+}
+
+/// Renders the function as a PNG, recording the expression and options in
+/// its metadata if `metadata` is set so the image can be reloaded later
+pub fn color_png(
+    width: usize,
+    height: usize,
+    fun_str: String,
+    options: domain_color::DCOptions,
+    metadata: bool,
+) -> Result<Vec<u8>> {
+    Ok(domain_color::color_png(
+        width, height, &fun_str, options, metadata,
+    )?)
+}
+
+/// Reads the expression and options stored in a PNG by `color_png`
+pub fn png_metadata(png: Vec<u8>) -> Result<domain_color::PngMetadata> {
+    Ok(domain_color::PngMetadata::from_png(&png)?)
+}
+
+/// A problem in the expression typed by the user.
+///
+/// `start` and `end` are UTF-16 code unit offsets into the expression, so they
+/// can be used directly as a Dart `TextRange`.
+pub struct ExprError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Parses `fun_str`, returning the first error found or `None` if it is valid
+pub fn check_expr(fun_str: String) -> Option<ExprError> {
+    let err = parser::parse(&fun_str).err()?;
+    let span = err.span();
+    let utf16_offset = |byte_offset: usize| fun_str[..byte_offset].encode_utf16().count();
+    Some(ExprError {
+        message: err.to_string(),
+        start: utf16_offset(span.start),
+        end: utf16_offset(span.end),
+    })
+}
+
+/// Names of the parameters used in `fun_str`, in order of first appearance,
+/// so the app can offer a control for each of them
+pub fn expr_params(fun_str: String) -> Result<Vec<String>> {
+    Ok(parser::parse_to_program(&fun_str)?.params().to_vec())
 }
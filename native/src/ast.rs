@@ -1,13 +1,20 @@
+use crate::context::Context;
 use crate::lexer::{Function, Token};
+use crate::special;
 use num::Complex;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
 use std::{collections::VecDeque, fmt};
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Node {
     Const {
         val: Complex<f64>,
     },
     Var,
+    Param {
+        name: String,
+    },
     Binary {
         op: Token,
         left: Option<Box<Node>>,
@@ -19,7 +26,7 @@ pub enum Node {
     },
     Fun {
         fun: Function,
-        arg: Option<Box<Node>>,
+        args: Vec<Node>,
     },
 }
 
@@ -28,29 +35,123 @@ impl fmt::Display for Node {
         match self {
             Node::Const { val } => write!(f, "{}", val),
             Node::Var => write!(f, "z"),
+            Node::Param { name } => write!(f, "{}", name),
             Node::Binary {
                 op,
                 left: _,
                 right: _,
             } => write!(f, "{}", op),
             Node::Unary { op, child: _ } => write!(f, "{}", op),
-            Node::Fun { fun, arg: _ } => write!(f, "{}", fun),
+            Node::Fun { fun, args: _ } => write!(f, "{}", fun),
+        }
+    }
+}
+
+impl Function {
+    /// Numbers of arguments the function accepts
+    pub fn arity(self) -> RangeInclusive<usize> {
+        match self {
+            Function::Pow | Function::Atan2 => 2..=2,
+            Function::Log => 1..=2,
+            _ => 1..=1,
+        }
+    }
+
+    /// Evaluates the function at `z`.
+    ///
+    /// `Sqrt` is the principal square root, with its branch cut along the
+    /// negative real axis (continuous from above, as in `num`). The reciprocal
+    /// functions are computed from their definitions, e.g. `cot(z) = cos(z) / sin(z)`,
+    /// so they have poles wherever the denominator vanishes. `Re`, `Im`, `abs`
+    /// and `arg` return real-valued complex numbers, with `arg` in `(-π, π]`.
+    ///
+    /// `log`/`ln` and the inverse functions use the principal branches from
+    /// `num`: the cut of `ln` is the negative real axis, `asin` and `acos` are
+    /// cut along the real axis outside `[-1, 1]`, `atan` and `asinh` along the
+    /// imaginary axis outside `[-i, i]`, `acosh` along the real axis left of `1`,
+    /// and `atanh` along the real axis outside `[-1, 1]`.
+    pub fn apply(self, z: Complex<f64>) -> Complex<f64> {
+        match self {
+            Function::Sqrt => z.sqrt(),
+            Function::Exp => z.exp(),
+            Function::Sin => z.sin(),
+            Function::Cos => z.cos(),
+            Function::Tan => z.tan(),
+            Function::Cot => z.cos() / z.sin(),
+            Function::Sec => z.cos().inv(),
+            Function::Csc => z.sin().inv(),
+            Function::Sinh => z.sinh(),
+            Function::Cosh => z.cosh(),
+            Function::Tanh => z.tanh(),
+            Function::Coth => z.cosh() / z.sinh(),
+            Function::Sech => z.cosh().inv(),
+            Function::Csch => z.sinh().inv(),
+            Function::Re => Complex::new(z.re, 0.0),
+            Function::Im => Complex::new(z.im, 0.0),
+            Function::Log | Function::Ln => z.ln(),
+            Function::Asin => z.asin(),
+            Function::Acos => z.acos(),
+            Function::Atan => z.atan(),
+            Function::Asinh => z.asinh(),
+            Function::Acosh => z.acosh(),
+            Function::Atanh => z.atanh(),
+            Function::Abs => Complex::new(z.norm(), 0.0),
+            Function::Arg => Complex::new(z.arg(), 0.0),
+            Function::Conj => z.conj(),
+            Function::Gamma => special::gamma(z),
+            Function::Digamma => special::digamma(z),
+            Function::Zeta => special::zeta(z),
+            Function::Pow | Function::Atan2 => panic!("Error in function evaluation ({self} takes two arguments), please report this to program maintainer"),
+        }
+    }
+
+    /// Evaluates a function of two arguments.
+    ///
+    /// `log(z, b)` is the logarithm of `z` in base `b`, using the principal
+    /// branch of the natural logarithm for both. `atan2(y, x)` is the argument
+    /// of `x + iy`, continued to complex `x` and `y` as `-i log((x + iy) / sqrt(x^2 + y^2))`,
+    /// so it matches the real `atan2` when both are real.
+    pub fn apply2(self, a: Complex<f64>, b: Complex<f64>) -> Complex<f64> {
+        match self {
+            Function::Pow => a.powc(b),
+            Function::Log => a.ln() / b.ln(),
+            Function::Atan2 => {
+                let (y, x) = (a, b);
+                let i = Complex::<f64>::i();
+                -i * ((x + i * y) / (x * x + y * y).sqrt()).ln()
+            }
+            _ => panic!("Error in function evaluation ({self} takes one argument), please report this to program maintainer"),
         }
     }
 }
 
 impl Node {
-    pub fn to_closure<'a>(self) -> Box<dyn Fn(Complex<f64>) -> Complex<f64> + 'a> {
+    pub fn to_closure<'a>(self) -> Box<dyn Fn(Complex<f64>) -> Complex<f64> + Send + Sync + 'a> {
+        self.to_closure_with(&Context::default())
+    }
+
+    /// Builds a closure with the parameters bound to their values in `ctx`.
+    /// Parameters missing from `ctx` evaluate to NaN.
+    pub fn to_closure_with<'a>(
+        self,
+        ctx: &Context,
+    ) -> Box<dyn Fn(Complex<f64>) -> Complex<f64> + Send + Sync + 'a> {
         match self {
             Node::Const { val } => Box::new(move |_z| val),
             Node::Var => Box::new(|z| z),
+            Node::Param { name } => {
+                let val = ctx
+                    .get(&name)
+                    .unwrap_or(Complex::new(f64::NAN, f64::NAN));
+                Box::new(move |_z| val)
+            }
             Node::Binary {
                 op: Token::Add,
                 left,
                 right,
             } => {
-                let left_fun = left.unwrap().to_closure();
-                let right_fun = right.unwrap().to_closure();
+                let left_fun = left.unwrap().to_closure_with(ctx);
+                let right_fun = right.unwrap().to_closure_with(ctx);
                 Box::new(move |z| left_fun(z) + right_fun(z))
             }
             Node::Binary {
@@ -58,8 +159,8 @@ impl Node {
                 left,
                 right,
             } => {
-                let left_fun = left.unwrap().to_closure();
-                let right_fun = right.unwrap().to_closure();
+                let left_fun = left.unwrap().to_closure_with(ctx);
+                let right_fun = right.unwrap().to_closure_with(ctx);
                 Box::new(move |z| left_fun(z) - right_fun(z))
             }
             Node::Binary {
@@ -67,8 +168,8 @@ impl Node {
                 left,
                 right,
             } => {
-                let left_fun = left.unwrap().to_closure();
-                let right_fun = right.unwrap().to_closure();
+                let left_fun = left.unwrap().to_closure_with(ctx);
+                let right_fun = right.unwrap().to_closure_with(ctx);
                 Box::new(move |z| left_fun(z) * right_fun(z))
             }
             Node::Binary {
@@ -76,17 +177,17 @@ impl Node {
                 left,
                 right,
             } => {
-                let left_fun = left.unwrap().to_closure();
-                let right_fun = right.unwrap().to_closure();
-                Box::new(move |z| left_fun(z) / right_fun(z))
+                let left_fun = left.unwrap().to_closure_with(ctx);
+                let right_fun = right.unwrap().to_closure_with(ctx);
+                Box::new(move |z| special::div(left_fun(z), right_fun(z)))
             }
             Node::Binary {
                 op: Token::Pow,
                 left,
                 right,
             } => {
-                let left_fun = left.unwrap().to_closure();
-                let right_fun = right.unwrap().to_closure();
+                let left_fun = left.unwrap().to_closure_with(ctx);
+                let right_fun = right.unwrap().to_closure_with(ctx);
                 Box::new(move |z| left_fun(z).powc(right_fun(z)))
             }
             Node::Binary {
@@ -98,17 +199,74 @@ impl Node {
                 op: Token::Sub,
                 child,
             } => {
-                let child_fun = child.unwrap().to_closure();
+                let child_fun = child.unwrap().to_closure_with(ctx);
                 Box::new(move |z| -child_fun(z))
             }
             Node::Unary { op: _, child: _ } => panic!("Error in closure construction (invalid unary operator), please report this to program maintainer"),
-            Node::Fun { fun: _, arg: _ } => Box::new(move |z| z),
+            Node::Fun { fun, args } => {
+                let mut arg_funs = args.into_iter().map(|arg| arg.to_closure_with(ctx));
+                match (arg_funs.next(), arg_funs.next()) {
+                    (Some(arg_fun), None) => Box::new(move |z| fun.apply(arg_fun(z))),
+                    (Some(a_fun), Some(b_fun)) => Box::new(move |z| fun.apply2(a_fun(z), b_fun(z))),
+                    _ => panic!("Error in closure construction (invalid argument count), please report this to program maintainer"),
+                }
+            }
         }
     }
 
-    pub fn to_mermaid(&self) -> String {
-        let mut nodes = Vec::new();
-        let mut edges = Vec::new();
+    /// Replaces each parameter named in `names` with the corresponding node of `args`
+    pub fn substitute(&self, names: &[String], args: &[Node]) -> Node {
+        let sub = |node: &Option<Box<Node>>| {
+            node.as_ref()
+                .map(|node| Box::new(node.substitute(names, args)))
+        };
+        match self {
+            Node::Param { name } => match names.iter().position(|param| param == name) {
+                Some(idx) => args[idx].clone(),
+                None => self.clone(),
+            },
+            Node::Const { val: _ } | Node::Var => self.clone(),
+            Node::Binary { op, left, right } => Node::Binary {
+                op: *op,
+                left: sub(left),
+                right: sub(right),
+            },
+            Node::Unary { op, child } => Node::Unary {
+                op: *op,
+                child: sub(child),
+            },
+            Node::Fun {
+                fun,
+                args: fun_args,
+            } => Node::Fun {
+                fun: *fun,
+                args: fun_args
+                    .iter()
+                    .map(|arg| arg.substitute(names, args))
+                    .collect(),
+            },
+        }
+    }
+
+    /// Whether the parameter `name` appears anywhere in the tree
+    pub fn uses_param(&self, name: &str) -> bool {
+        match self {
+            Node::Param { name: param } => param == name,
+            Node::Const { val: _ } | Node::Var => false,
+            Node::Binary { op: _, left, right } => [left, right]
+                .iter()
+                .any(|child| child.as_ref().is_some_and(|child| child.uses_param(name))),
+            Node::Unary { op: _, child } => {
+                child.as_ref().is_some_and(|child| child.uses_param(name))
+            }
+            Node::Fun { fun: _, args } => args.iter().any(|arg| arg.uses_param(name)),
+        }
+    }
+
+    /// Numbers the nodes in breadth-first order, pairing each with its
+    /// number and the number of its parent (the root is its own parent)
+    fn numbered(&self) -> Vec<(usize, &Node, usize)> {
+        let mut numbered = Vec::new();
         let mut counter: usize = 0;
         let mut queue = VecDeque::from([(self, counter)]);
         let mut curr;
@@ -117,19 +275,29 @@ impl Node {
             match curr.0 {
                 Node::Const { val: _ } => (),
                 Node::Var => (),
+                Node::Param { name: _ } => (),
                 Node::Binary { op: _, left, right } => {
                     queue.push_back((left.as_ref().unwrap(), counter));
                     queue.push_back((right.as_ref().unwrap(), counter));
                 }
                 Node::Unary { op: _, child } => queue.push_back((child.as_ref().unwrap(), counter)),
-                Node::Fun { fun: _, arg } => queue.push_back((arg.as_ref().unwrap(), counter)),
+                Node::Fun { fun: _, args } => queue.extend(args.iter().map(|arg| (arg, counter))),
             }
-            nodes.push(format!("{counter}[{}]", curr.0));
+            numbered.push((counter, curr.0, curr.1));
+            counter += 1;
+        }
+        numbered
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut nodes = Vec::new();
+        let mut edges = Vec::new();
+        for (id, node, parent) in self.numbered() {
+            nodes.push(format!("{id}[{node}]"));
             // We don't want an incoming edge for the root node
-            if counter != 0 {
-                edges.push(format!("{} --> {}", curr.1, counter));
+            if id != 0 {
+                edges.push(format!("{parent} --> {id}"));
             }
-            counter += 1;
         }
         [
             "flowchart TD".to_string(),
@@ -138,4 +306,56 @@ impl Node {
         ]
         .join("\n    ")
     }
+
+    /// Graphviz DOT description of the tree, with nodes numbered as in [`Node::to_mermaid`]
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec!["digraph {".to_string()];
+        let mut edges = Vec::new();
+        for (id, node, parent) in self.numbered() {
+            let label = node.to_string().replace('\\', "\\\\").replace('"', "\\\"");
+            lines.push(format!("    {id} [label=\"{label}\"];"));
+            if id != 0 {
+                edges.push(format!("    {parent} -> {id};"));
+            }
+        }
+        lines.extend(edges);
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+
+    /// Serializes the tree to JSON. Non-finite constants are written as `null`,
+    /// so trees containing them do not deserialize back.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Node serialization cannot fail")
+    }
+
+    /// Reads a tree written by [`Node::to_json`], rejecting trees the parser
+    /// could not have produced, such as operators with missing operands or
+    /// calls with the wrong number of arguments
+    pub fn from_json(json: &str) -> Result<Node, serde_json::Error> {
+        let node: Node = serde_json::from_str(json)?;
+        node.check_shape().map_err(serde::de::Error::custom)?;
+        Ok(node)
+    }
+
+    fn check_shape(&self) -> Result<(), String> {
+        match self {
+            Node::Const { val: _ } | Node::Var | Node::Param { name: _ } => Ok(()),
+            Node::Binary {
+                op: Token::Add | Token::Sub | Token::Mult | Token::Div | Token::Pow,
+                left: Some(left),
+                right: Some(right),
+            } => left.check_shape().and_then(|_| right.check_shape()),
+            Node::Binary { op, .. } => Err(format!("invalid binary operation {op}")),
+            Node::Unary {
+                op: Token::Sub,
+                child: Some(child),
+            } => child.check_shape(),
+            Node::Unary { op, .. } => Err(format!("invalid unary operation {op}")),
+            Node::Fun { fun, args } if fun.arity().contains(&args.len()) => {
+                args.iter().try_for_each(Node::check_shape)
+            }
+            Node::Fun { fun, args } => Err(format!("{fun} called with {} arguments", args.len())),
+        }
+    }
 }
@@ -0,0 +1,219 @@
+use crate::image::Image;
+use std::fmt;
+
+/// Layout of the pixels in an encoded BMP
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BmpOptions {
+    /// Stores rows from the top down, written as a negative height, instead of
+    /// the usual bottom-up order
+    pub top_down: bool,
+    /// Writes 32-bit BGRA pixels with a `BITMAPV4HEADER` describing the alpha
+    /// channel, instead of 24-bit BGR
+    pub alpha: bool,
+}
+
+const FILE_HEADER_SIZE: usize = 14;
+/// `BITMAPINFOHEADER`
+const INFO_HEADER_SIZE: usize = 40;
+/// `BITMAPV4HEADER`, which adds channel masks and a color space
+const V4_HEADER_SIZE: usize = 108;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+/// Channel masks of BGRA pixels read as little endian words
+const BGRA_MASKS: [u32; 4] = [0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0xff00_0000];
+/// `LCS_sRGB`
+const SRGB_COLOR_SPACE: u32 = 0x7352_4742;
+
+impl BmpOptions {
+    fn bytes_per_pixel(self) -> usize {
+        if self.alpha {
+            4
+        } else {
+            3
+        }
+    }
+
+    fn header_size(self) -> usize {
+        FILE_HEADER_SIZE
+            + if self.alpha {
+                V4_HEADER_SIZE
+            } else {
+                INFO_HEADER_SIZE
+            }
+    }
+}
+
+/// Bytes in a row of pixels, padded to a multiple of 4
+fn row_size(width: usize, bytes_per_pixel: usize) -> Option<usize> {
+    Some(width.checked_mul(bytes_per_pixel)?.checked_add(3)? / 4 * 4)
+}
+
+/// Size of the BMP file for an image of the given dimensions, or `None` if
+/// the image cannot be stored in a BMP, whose sizes are 32-bit fields
+pub fn file_size(width: usize, height: usize, options: BmpOptions) -> Option<usize> {
+    let size = row_size(width, options.bytes_per_pixel())?
+        .checked_mul(height)?
+        .checked_add(options.header_size())?;
+    let fits =
+        size <= u32::MAX as usize && i32::try_from(width).is_ok() && i32::try_from(height).is_ok();
+    fits.then_some(size)
+}
+
+/// Encodes the image as an uncompressed BMP.
+///
+/// Panics if the image is too large for a BMP, see [`file_size`].
+pub fn encode(image: &Image, options: BmpOptions) -> Vec<u8> {
+    let (width, height) = (image.width, image.height);
+    let file_size = file_size(width, height, options).expect("Image too large for a BMP");
+    let header_size = options.header_size();
+    let bytes_per_pixel = options.bytes_per_pixel();
+    let row_size = row_size(width, bytes_per_pixel).unwrap();
+
+    let mut bmp = Vec::with_capacity(file_size);
+    // File header
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&(file_size as u32).to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]); // Reserved
+    bmp.extend_from_slice(&(header_size as u32).to_le_bytes()); // Pixel array offset
+
+    // DIB header
+    let height_field = if options.top_down {
+        -(height as i32)
+    } else {
+        height as i32
+    };
+    bmp.extend_from_slice(&((header_size - FILE_HEADER_SIZE) as u32).to_le_bytes());
+    bmp.extend_from_slice(&(width as i32).to_le_bytes());
+    bmp.extend_from_slice(&height_field.to_le_bytes());
+    bmp.extend_from_slice(&1u16.to_le_bytes()); // Color planes
+    bmp.extend_from_slice(&(8 * bytes_per_pixel as u16).to_le_bytes()); // Bits per pixel
+    let compression = if options.alpha { BI_BITFIELDS } else { BI_RGB };
+    bmp.extend_from_slice(&compression.to_le_bytes());
+    bmp.extend_from_slice(&((row_size * height) as u32).to_le_bytes()); // Pixel array size
+    bmp.extend_from_slice(&2835u32.to_le_bytes()); // Horizontal pixels per meter, 72 DPI
+    bmp.extend_from_slice(&2835u32.to_le_bytes()); // Vertical pixels per meter, 72 DPI
+    bmp.extend_from_slice(&[0; 8]); // No palette, all colors important
+    if options.alpha {
+        for mask in BGRA_MASKS {
+            bmp.extend_from_slice(&mask.to_le_bytes());
+        }
+        bmp.extend_from_slice(&SRGB_COLOR_SPACE.to_le_bytes());
+        // Endpoints and gamma, unused for sRGB
+        bmp.resize(header_size, 0);
+    }
+    debug_assert_eq!(bmp.len(), header_size);
+
+    // Pixel array, BGR(A) with each row padded with zeros
+    let mut write_row = |row: &[u8]| {
+        for rgba in row.chunks_exact(4) {
+            bmp.extend_from_slice(&[rgba[2], rgba[1], rgba[0], rgba[3]][..bytes_per_pixel]);
+        }
+        bmp.resize(bmp.len() + row_size - bytes_per_pixel * width, 0);
+    };
+    if options.top_down {
+        image.rows().for_each(&mut write_row);
+    } else {
+        image.rows().rev().for_each(&mut write_row);
+    }
+    debug_assert_eq!(bmp.len(), file_size);
+    bmp
+}
+
+/// Reasons a BMP cannot be decoded
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BmpError {
+    /// The data does not start with a BMP header
+    InvalidHeader,
+    /// The file ends before the end of the pixel array
+    Truncated,
+    /// The BMP uses a feature other than uncompressed 24 or 32-bit pixels
+    Unsupported,
+}
+
+impl fmt::Display for BmpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BmpError::InvalidHeader => write!(f, "Invalid BMP header"),
+            BmpError::Truncated => write!(f, "Truncated BMP data"),
+            BmpError::Unsupported => write!(f, "Unsupported BMP format"),
+        }
+    }
+}
+
+impl std::error::Error for BmpError {}
+
+/// Decodes an uncompressed 24 or 32-bit BMP, such as those written by [`encode`].
+///
+/// 32-bit pixels keep their alpha only when the header gives BGRA channel
+/// masks, otherwise they are opaque.
+pub fn decode(bmp: &[u8]) -> Result<Image, BmpError> {
+    let u16_at = |offset: usize| -> Result<u16, BmpError> {
+        let bytes = bmp.get(offset..offset + 2).ok_or(BmpError::Truncated)?;
+        Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    let u32_at = |offset: usize| -> Result<u32, BmpError> {
+        let bytes = bmp.get(offset..offset + 4).ok_or(BmpError::Truncated)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    };
+
+    if !bmp.starts_with(b"BM") {
+        return Err(BmpError::InvalidHeader);
+    }
+    let pixels_offset = u32_at(10)? as usize;
+    let dib_size = u32_at(14)? as usize;
+    if dib_size < INFO_HEADER_SIZE {
+        return Err(BmpError::Unsupported);
+    }
+    let width = u32_at(18)? as i32;
+    let height = u32_at(22)? as i32;
+    let bits_per_pixel = u16_at(28)?;
+    let compression = u32_at(30)?;
+    if width <= 0 || height == 0 {
+        return Err(BmpError::InvalidHeader);
+    }
+    let bytes_per_pixel = match (bits_per_pixel, compression) {
+        (24, BI_RGB) | (32, BI_RGB) => bits_per_pixel as usize / 8,
+        (32, BI_BITFIELDS) => {
+            // Masks follow the header, or are part of it from BITMAPV4HEADER
+            let masks = [u32_at(54)?, u32_at(58)?, u32_at(62)?];
+            if masks != BGRA_MASKS[..3] {
+                return Err(BmpError::Unsupported);
+            }
+            4
+        }
+        _ => return Err(BmpError::Unsupported),
+    };
+    let has_alpha = compression == BI_BITFIELDS
+        && dib_size >= 56
+        && u32_at(FILE_HEADER_SIZE + 52)? == BGRA_MASKS[3];
+
+    let top_down = height < 0;
+    let (width, height) = (width as usize, height.unsigned_abs() as usize);
+    let row_size = row_size(width, bytes_per_pixel).ok_or(BmpError::Unsupported)?;
+    let pixels_end = row_size
+        .checked_mul(height)
+        .and_then(|size| size.checked_add(pixels_offset))
+        .ok_or(BmpError::Unsupported)?;
+    let pixel_array = bmp
+        .get(pixels_offset..pixels_end)
+        .ok_or(BmpError::Truncated)?;
+
+    let mut image = Image::new(width, height);
+    for (y, row) in image.pixels.chunks_exact_mut(4 * width).enumerate() {
+        let src_row = if top_down { y } else { height - 1 - y };
+        let src = &pixel_array[src_row * row_size..][..bytes_per_pixel * width];
+        for (rgba, bgr) in row
+            .chunks_exact_mut(4)
+            .zip(src.chunks_exact(bytes_per_pixel))
+        {
+            rgba[0] = bgr[2];
+            rgba[1] = bgr[1];
+            rgba[2] = bgr[0];
+            if has_alpha {
+                rgba[3] = bgr[3];
+            }
+        }
+    }
+    Ok(image)
+}
@@ -0,0 +1,238 @@
+use crate::ast::Node;
+use crate::context::{Context, UnboundParameter};
+use crate::lexer::{Function, Token};
+use crate::special;
+use num::Complex;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Index of a register in a [`Program`]
+pub type Reg = u32;
+
+/// A single instruction of a compiled expression.
+///
+/// Instruction `i` always writes its result to register `i`, so operands only
+/// ever refer to earlier instructions and the result of the program is the
+/// value of the last register.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Instr {
+    Const(Complex<f64>),
+    Var,
+    /// Value of the parameter in the given slot of [`Program::params`]
+    Param(u32),
+    Add(Reg, Reg),
+    Sub(Reg, Reg),
+    Mult(Reg, Reg),
+    Div(Reg, Reg),
+    Pow(Reg, Reg),
+    Neg(Reg),
+    Fun(Function, Reg),
+    Fun2(Function, Reg, Reg),
+}
+
+/// An expression compiled into a flat list of register instructions.
+///
+/// Evaluating a `Program` is a single loop over a contiguous instruction
+/// buffer, rather than the chain of boxed closures built by [`Node::to_closure`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    instrs: Vec<Instr>,
+    params: Vec<String>,
+}
+
+impl Program {
+    /// Compiles `node`, evaluating each distinct subexpression only once
+    pub fn compile(node: &Node) -> Program {
+        let mut compiler = Compiler {
+            program: Program {
+                instrs: Vec::new(),
+                params: Vec::new(),
+            },
+            emitted: HashMap::new(),
+        };
+        // A node never equals one of its own subtrees, so the root is always
+        // emitted last even when its children are shared
+        compiler.emit_node(node);
+        compiler.program
+    }
+
+    pub fn instrs(&self) -> &[Instr] {
+        &self.instrs
+    }
+
+    /// Names of the parameters used by the program, in slot order
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    /// Looks up the value of each parameter in `ctx`, producing the slot
+    /// values to evaluate the program with
+    pub fn bind(&self, ctx: &Context) -> Result<Vec<Complex<f64>>, UnboundParameter> {
+        self.params
+            .iter()
+            .map(|name| {
+                ctx.get(name)
+                    .ok_or_else(|| UnboundParameter { name: name.clone() })
+            })
+            .collect()
+    }
+
+    /// Number of registers needed to evaluate the program
+    pub fn len(&self) -> usize {
+        self.instrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instrs.is_empty()
+    }
+
+    /// Evaluates the program at `z`, using `regs` as scratch space.
+    ///
+    /// `params` holds the parameter values in slot order, as produced by
+    /// [`Program::bind`]. Reusing the same `regs` between calls avoids
+    /// allocating per evaluation.
+    pub fn eval_with(
+        &self,
+        z: Complex<f64>,
+        params: &[Complex<f64>],
+        regs: &mut Vec<Complex<f64>>,
+    ) -> Complex<f64> {
+        regs.clear();
+        for instr in &self.instrs {
+            let val = match *instr {
+                Instr::Const(val) => val,
+                Instr::Var => z,
+                Instr::Param(slot) => params[slot as usize],
+                Instr::Add(a, b) => regs[a as usize] + regs[b as usize],
+                Instr::Sub(a, b) => regs[a as usize] - regs[b as usize],
+                Instr::Mult(a, b) => regs[a as usize] * regs[b as usize],
+                Instr::Div(a, b) => special::div(regs[a as usize], regs[b as usize]),
+                Instr::Pow(a, b) => regs[a as usize].powc(regs[b as usize]),
+                Instr::Neg(a) => -regs[a as usize],
+                Instr::Fun(fun, a) => fun.apply(regs[a as usize]),
+                Instr::Fun2(fun, a, b) => fun.apply2(regs[a as usize], regs[b as usize]),
+            };
+            regs.push(val);
+        }
+        *regs.last().expect("Cannot evaluate an empty program")
+    }
+
+    /// Evaluates the program at `z`, allocating its own registers
+    pub fn eval(&self, z: Complex<f64>, params: &[Complex<f64>]) -> Complex<f64> {
+        self.eval_with(z, params, &mut Vec::with_capacity(self.len()))
+    }
+}
+
+/// Identity of an instruction for common subexpression elimination, comparing
+/// constants by their bits so that it can be hashed
+struct InstrKey(Instr);
+
+impl PartialEq for InstrKey {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.0, other.0) {
+            (Instr::Const(a), Instr::Const(b)) => {
+                a.re.to_bits() == b.re.to_bits() && a.im.to_bits() == b.im.to_bits()
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl Eq for InstrKey {}
+
+impl Hash for InstrKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(&self.0).hash(state);
+        match self.0 {
+            Instr::Const(val) => {
+                val.re.to_bits().hash(state);
+                val.im.to_bits().hash(state);
+            }
+            Instr::Var => (),
+            Instr::Param(slot) => slot.hash(state),
+            Instr::Add(a, b)
+            | Instr::Sub(a, b)
+            | Instr::Mult(a, b)
+            | Instr::Div(a, b)
+            | Instr::Pow(a, b) => (a, b).hash(state),
+            Instr::Neg(a) => a.hash(state),
+            Instr::Fun(fun, a) => (fun, a).hash(state),
+            Instr::Fun2(fun, a, b) => (fun, a, b).hash(state),
+        }
+    }
+}
+
+struct Compiler {
+    program: Program,
+    /// Register holding the result of each instruction emitted so far
+    emitted: HashMap<InstrKey, Reg>,
+}
+
+impl Compiler {
+    /// Appends `instr`, unless an identical instruction has already been
+    /// emitted, in which case its register is reused
+    fn push(&mut self, instr: Instr) -> Reg {
+        // Addition and multiplication commute exactly, so their operands are
+        // ordered to let `a + b` and `b + a` share a register
+        let instr = match instr {
+            Instr::Add(a, b) => Instr::Add(a.min(b), a.max(b)),
+            Instr::Mult(a, b) => Instr::Mult(a.min(b), a.max(b)),
+            instr => instr,
+        };
+        let instrs = &mut self.program.instrs;
+        *self.emitted.entry(InstrKey(instr)).or_insert_with(|| {
+            instrs.push(instr);
+            (instrs.len() - 1) as Reg
+        })
+    }
+
+    fn emit_node(&mut self, node: &Node) -> Reg {
+        match node {
+            Node::Const { val } => self.push(Instr::Const(*val)),
+            Node::Var => self.push(Instr::Var),
+            Node::Param { name } => {
+                let params = &mut self.program.params;
+                let slot = match params.iter().position(|param| param == name) {
+                    Some(slot) => slot,
+                    None => {
+                        params.push(name.clone());
+                        params.len() - 1
+                    }
+                };
+                self.push(Instr::Param(slot as u32))
+            }
+            Node::Binary { op, left, right } => {
+                let a = self.emit_node(left.as_ref().unwrap());
+                let b = self.emit_node(right.as_ref().unwrap());
+                self.push(match op {
+                    Token::Add => Instr::Add(a, b),
+                    Token::Sub => Instr::Sub(a, b),
+                    Token::Mult => Instr::Mult(a, b),
+                    Token::Div => Instr::Div(a, b),
+                    Token::Pow => Instr::Pow(a, b),
+                    _ => panic!("Error in bytecode compilation (invalid binary operator), please report this to program maintainer"),
+                })
+            }
+            Node::Unary {
+                op: Token::Sub,
+                child,
+            } => {
+                let a = self.emit_node(child.as_ref().unwrap());
+                self.push(Instr::Neg(a))
+            }
+            Node::Unary { op: _, child: _ } => panic!("Error in bytecode compilation (invalid unary operator), please report this to program maintainer"),
+            Node::Fun { fun, args } => match args.as_slice() {
+                [arg] => {
+                    let a = self.emit_node(arg);
+                    self.push(Instr::Fun(*fun, a))
+                }
+                [arg_a, arg_b] => {
+                    let a = self.emit_node(arg_a);
+                    let b = self.emit_node(arg_b);
+                    self.push(Instr::Fun2(*fun, a, b))
+                }
+                _ => panic!("Error in bytecode compilation (invalid argument count), please report this to program maintainer"),
+            },
+        }
+    }
+}
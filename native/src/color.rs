@@ -0,0 +1,245 @@
+use hsluv;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[allow(dead_code)]
+impl Rgb {
+    /// Converts channels from 0 to 1 to bytes, clamping values outside that
+    /// range and rounding to the nearest byte. NaN channels become 0.
+    pub fn from_linear(r: f64, g: f64, b: f64) -> Self {
+        let byte = |c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Rgb {
+            r: byte(r),
+            g: byte(g),
+            b: byte(b),
+        }
+    }
+
+    /// Channels in linear light, from 0 to 1, undoing the sRGB transfer function
+    pub fn to_linear_light(self) -> [f64; 3] {
+        [self.r, self.g, self.b].map(|c| srgb_decode(c as f64 / 255.0))
+    }
+
+    /// Encodes channels in linear light, from 0 to 1, as sRGB, rounding to the
+    /// nearest byte
+    pub fn from_linear_light(rgb: [f64; 3]) -> Self {
+        let [r, g, b] = rgb.map(|c| (srgb_encode(c.clamp(0.0, 1.0)) * 255.0).round() as u8);
+        Rgb { r, g, b }
+    }
+}
+
+/// Maps the phase and modulus of a function value to a color.
+///
+/// `hue` is the argument of the value in degrees, in `[0, 360)`, and
+/// `lightness` is its modulus mapped into `[0, 1]`, with `0` for zeros and
+/// `1` for poles.
+pub trait ColorScheme {
+    fn color(&self, hue: f64, lightness: f64) -> Rgb;
+}
+
+/// HSV with full saturation, using the lightness as the value
+pub struct Hsv;
+
+/// HSLuv, perceptually uniform lightness with hue-dependent saturation
+pub struct Hsluv;
+
+/// HPLuv, perceptually uniform lightness with pastel colors of equal chroma
+pub struct Hpluv;
+
+/// OKLCH, perceptually uniform in lightness, chroma and hue
+pub struct Oklch;
+
+/// Shows only the modulus, as a gray level
+pub struct Grayscale;
+
+/// Shows only the phase, at constant lightness
+pub struct Phase;
+
+/// Built-in color schemes, selectable through `DCOptions`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Scheme {
+    Hsv,
+    Hsluv,
+    #[default]
+    Hpluv,
+    Oklch,
+    Grayscale,
+    Phase,
+}
+
+/// Maps the modulus of a function value to the lightness passed to a
+/// [`ColorScheme`]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ModulusMap {
+    /// `|f| / (|f| + 1)`
+    #[default]
+    Rational,
+    /// `|f|^exponent / (|f|^exponent + 1)`, steeper around `|f| = 1` for
+    /// exponents above 1 and softer below
+    Exponent { exponent: f64 },
+    /// `2 / π arctan |f|`
+    Arctan,
+    /// Bands that grow lighter as `|f|` increases and start over each time it
+    /// is multiplied by `base`, showing the modulus at every scale
+    LogSawtooth { base: f64 },
+    /// Enhanced phase portrait after Wegert: the modulus bands of
+    /// `LogSawtooth` shaded together with `phase_sectors` bands per full turn
+    /// of the phase, so that the plot shows a tiling of conformal cells.
+    /// `0` sectors shades the modulus only.
+    Enhanced { base: f64, phase_sectors: u32 },
+}
+
+impl ModulusMap {
+    /// Lightness in `[0, 1]` of a value with modulus `modulus` and argument
+    /// `hue` in degrees, in `[0, 360)`
+    pub fn lightness(&self, modulus: f64, hue: f64) -> f64 {
+        if modulus.is_infinite() {
+            return 1.0;
+        }
+        // Position within a band, from 0 to 1
+        let sawtooth = |x: f64| x - x.floor();
+        // Darkens the start of each band, as in Wegert's enhanced portraits
+        let shade = |t: f64| 0.6 + 0.4 * t;
+        match *self {
+            ModulusMap::Rational => modulus / (modulus + 1.0),
+            ModulusMap::Exponent { exponent } => {
+                let scaled = modulus.powf(exponent);
+                if scaled.is_finite() {
+                    scaled / (scaled + 1.0)
+                } else {
+                    1.0
+                }
+            }
+            ModulusMap::Arctan => modulus.atan() * std::f64::consts::FRAC_2_PI,
+            ModulusMap::LogSawtooth { base } => 0.35 + 0.45 * sawtooth(modulus.ln() / base.ln()),
+            ModulusMap::Enhanced {
+                base,
+                phase_sectors,
+            } => {
+                let modulus_shade = shade(sawtooth(modulus.ln() / base.ln()));
+                let phase_shade = if phase_sectors > 0 {
+                    shade(sawtooth(hue / 360.0 * phase_sectors as f64))
+                } else {
+                    1.0
+                };
+                0.2 + 0.65 * modulus_shade * phase_shade
+            }
+        }
+    }
+
+    /// Whether the bases are finite and greater than 1, and the exponent
+    /// finite and positive
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            ModulusMap::Rational | ModulusMap::Arctan => true,
+            ModulusMap::Exponent { exponent } => exponent.is_finite() && exponent > 0.0,
+            ModulusMap::LogSawtooth { base } | ModulusMap::Enhanced { base, .. } => {
+                base.is_finite() && base > 1.0
+            }
+        }
+    }
+}
+
+impl ColorScheme for Hsv {
+    fn color(&self, hue: f64, lightness: f64) -> Rgb {
+        let sector = hue / 60.0;
+        let x = 1.0 - (sector % 2.0 - 1.0).abs();
+        let (r, g, b) = match sector as u32 {
+            0 => (1.0, x, 0.0),
+            1 => (x, 1.0, 0.0),
+            2 => (0.0, 1.0, x),
+            3 => (0.0, x, 1.0),
+            4 => (x, 0.0, 1.0),
+            _ => (1.0, 0.0, x),
+        };
+        Rgb::from_linear(r * lightness, g * lightness, b * lightness)
+    }
+}
+
+impl ColorScheme for Hsluv {
+    fn color(&self, hue: f64, lightness: f64) -> Rgb {
+        let (r, g, b) = hsluv::hsluv_to_rgb(hue, 100.0, 100.0 * lightness);
+        Rgb::from_linear(r, g, b)
+    }
+}
+
+impl ColorScheme for Hpluv {
+    fn color(&self, hue: f64, lightness: f64) -> Rgb {
+        let (r, g, b) = hsluv::hpluv_to_rgb(hue, 100.0, 100.0 * lightness);
+        Rgb::from_linear(r, g, b)
+    }
+}
+
+impl ColorScheme for Oklch {
+    fn color(&self, hue: f64, lightness: f64) -> Rgb {
+        // Chroma tapers off towards black and white to stay mostly within sRGB
+        let chroma = 0.5 * lightness * (1.0 - lightness);
+        let (a, b) = (
+            chroma * hue.to_radians().cos(),
+            chroma * hue.to_radians().sin(),
+        );
+
+        // OKLab to linear sRGB, from https://bottosson.github.io/posts/oklab/
+        let l_ = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+        let m_ = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+        let s_ = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+        let r = 4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_;
+        let g = -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_;
+        let b = -0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_;
+
+        Rgb::from_linear(
+            srgb_encode(r.clamp(0.0, 1.0)),
+            srgb_encode(g.clamp(0.0, 1.0)),
+            srgb_encode(b.clamp(0.0, 1.0)),
+        )
+    }
+}
+
+impl ColorScheme for Grayscale {
+    fn color(&self, _hue: f64, lightness: f64) -> Rgb {
+        Rgb::from_linear(lightness, lightness, lightness)
+    }
+}
+
+impl ColorScheme for Phase {
+    fn color(&self, hue: f64, _lightness: f64) -> Rgb {
+        Hsluv.color(hue, 0.65)
+    }
+}
+
+impl ColorScheme for Scheme {
+    fn color(&self, hue: f64, lightness: f64) -> Rgb {
+        match self {
+            Scheme::Hsv => Hsv.color(hue, lightness),
+            Scheme::Hsluv => Hsluv.color(hue, lightness),
+            Scheme::Hpluv => Hpluv.color(hue, lightness),
+            Scheme::Oklch => Oklch.color(hue, lightness),
+            Scheme::Grayscale => Grayscale.color(hue, lightness),
+            Scheme::Phase => Phase.color(hue, lightness),
+        }
+    }
+}
+
+/// Undoes the sRGB transfer function, giving the linear value of an encoded
+/// channel in `[0, 1]`
+fn srgb_decode(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Applies the sRGB transfer function to a linear channel value in `[0, 1]`
+fn srgb_encode(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -0,0 +1,52 @@
+use num::Complex;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Values of the named parameters of an expression, e.g. `c` in `z^2 + c`.
+///
+/// A context is supplied when evaluating a compiled expression, so parameters
+/// can be changed without parsing the expression again.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Context {
+    values: HashMap<String, Complex<f64>>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Context::default()
+    }
+
+    /// Binds `name` to `value`, replacing any previous value
+    pub fn set(&mut self, name: &str, value: Complex<f64>) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Complex<f64>> {
+        self.values.get(name).copied()
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, Complex<f64>)> for Context {
+    fn from_iter<T: IntoIterator<Item = (S, Complex<f64>)>>(iter: T) -> Self {
+        Context {
+            values: iter
+                .into_iter()
+                .map(|(name, value)| (name.into(), value))
+                .collect(),
+        }
+    }
+}
+
+/// A parameter used by an expression has no value in the [`Context`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnboundParameter {
+    pub name: String,
+}
+
+impl fmt::Display for UnboundParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No value given for the parameter \"{}\"", self.name)
+    }
+}
+
+impl std::error::Error for UnboundParameter {}
@@ -0,0 +1,225 @@
+use crate::ast::Node;
+use crate::lexer::{Function, Token};
+use num::Complex;
+
+// Constructors for the nodes built by differentiation
+
+fn constant(re: f64) -> Node {
+    Node::Const {
+        val: Complex::new(re, 0.0),
+    }
+}
+
+fn binary(op: Token, left: Node, right: Node) -> Node {
+    Node::Binary {
+        op,
+        left: Some(Box::new(left)),
+        right: Some(Box::new(right)),
+    }
+}
+
+fn add(left: Node, right: Node) -> Node {
+    binary(Token::Add, left, right)
+}
+
+fn sub(left: Node, right: Node) -> Node {
+    binary(Token::Sub, left, right)
+}
+
+fn mult(left: Node, right: Node) -> Node {
+    binary(Token::Mult, left, right)
+}
+
+fn div(left: Node, right: Node) -> Node {
+    binary(Token::Div, left, right)
+}
+
+fn pow(left: Node, right: Node) -> Node {
+    binary(Token::Pow, left, right)
+}
+
+fn neg(child: Node) -> Node {
+    Node::Unary {
+        op: Token::Sub,
+        child: Some(Box::new(child)),
+    }
+}
+
+fn fun(fun: Function, arg: Node) -> Node {
+    Node::Fun {
+        fun,
+        args: vec![arg],
+    }
+}
+
+fn square(node: Node) -> Node {
+    pow(node, constant(2.0))
+}
+
+impl Node {
+    /// Builds the tree of the derivative with respect to `z`.
+    ///
+    /// Parameters are treated as constants. Functions that are not holomorphic
+    /// (`Re`, `Im`, `abs`, `arg` and `conj`) have no complex derivative, and
+    /// `digamma` and `zeta` have none in terms of the supported functions, so
+    /// their derivatives evaluate to NaN, unless their arguments don't depend
+    /// on `z`. The result is not simplified.
+    pub fn derivative(&self) -> Node {
+        // Constant subtrees are skipped, so that the chain rule doesn't
+        // multiply 0 by an undefined outer derivative, e.g. for abs(c) or ln(0)
+        if !self.uses_var() {
+            return constant(0.0);
+        }
+        match self {
+            Node::Const { val: _ } | Node::Param { name: _ } => constant(0.0),
+            Node::Var => constant(1.0),
+            Node::Binary { op, left, right } => {
+                let (left, right) = (left.as_deref().unwrap(), right.as_deref().unwrap());
+                match op {
+                    Token::Add => add(left.derivative(), right.derivative()),
+                    Token::Sub => sub(left.derivative(), right.derivative()),
+                    // Product rule
+                    Token::Mult => add(
+                        mult(left.derivative(), right.clone()),
+                        mult(left.clone(), right.derivative()),
+                    ),
+                    // Quotient rule
+                    Token::Div => div(
+                        sub(
+                            mult(left.derivative(), right.clone()),
+                            mult(left.clone(), right.derivative()),
+                        ),
+                        square(right.clone()),
+                    ),
+                    Token::Pow => pow_derivative(left, right),
+                    _ => panic!("Error in differentiation (invalid binary operator), please report this to program maintainer"),
+                }
+            }
+            Node::Unary {
+                op: Token::Sub,
+                child,
+            } => neg(child.as_deref().unwrap().derivative()),
+            Node::Unary { op: _, child: _ } => panic!("Error in differentiation (invalid unary operator), please report this to program maintainer"),
+            Node::Fun { fun, args } => match args.as_slice() {
+                [arg] => mult(fun_derivative(*fun, arg), arg.derivative()),
+                [a, b] => fun2_derivative(*fun, a, b),
+                _ => panic!("Error in differentiation (invalid argument count), please report this to program maintainer"),
+            },
+        }
+    }
+
+    /// Whether the tree depends on `z`
+    fn uses_var(&self) -> bool {
+        match self {
+            Node::Var => true,
+            Node::Const { val: _ } | Node::Param { name: _ } => false,
+            Node::Binary { op: _, left, right } => [left, right]
+                .iter()
+                .any(|child| child.as_ref().is_some_and(|child| child.uses_var())),
+            Node::Unary { op: _, child } => child.as_ref().is_some_and(|child| child.uses_var()),
+            Node::Fun { fun: _, args } => args.iter().any(|arg| arg.uses_var()),
+        }
+    }
+}
+
+/// Derivative of `base ^ exp`
+fn pow_derivative(base: &Node, exp: &Node) -> Node {
+    if !exp.uses_var() {
+        // Power rule, which unlike the general case is defined at base = 0
+        mult(
+            mult(
+                exp.clone(),
+                pow(base.clone(), sub(exp.clone(), constant(1.0))),
+            ),
+            base.derivative(),
+        )
+    } else if !base.uses_var() {
+        mult(
+            mult(
+                pow(base.clone(), exp.clone()),
+                fun(Function::Ln, base.clone()),
+            ),
+            exp.derivative(),
+        )
+    } else {
+        // d/dz a^b = a^b (b' ln a + b a' / a)
+        mult(
+            pow(base.clone(), exp.clone()),
+            add(
+                mult(exp.derivative(), fun(Function::Ln, base.clone())),
+                div(mult(exp.clone(), base.derivative()), base.clone()),
+            ),
+        )
+    }
+}
+
+/// Derivative of a function of one argument, evaluated at `u`
+fn fun_derivative(f: Function, u: &Node) -> Node {
+    let u = u.clone();
+    match f {
+        Function::Sqrt => div(constant(1.0), mult(constant(2.0), fun(Function::Sqrt, u))),
+        Function::Exp => fun(Function::Exp, u),
+        Function::Sin => fun(Function::Cos, u),
+        Function::Cos => neg(fun(Function::Sin, u)),
+        Function::Tan => square(fun(Function::Sec, u)),
+        Function::Cot => neg(square(fun(Function::Csc, u))),
+        Function::Sec => mult(fun(Function::Sec, u.clone()), fun(Function::Tan, u)),
+        Function::Csc => neg(mult(fun(Function::Csc, u.clone()), fun(Function::Cot, u))),
+        Function::Sinh => fun(Function::Cosh, u),
+        Function::Cosh => fun(Function::Sinh, u),
+        Function::Tanh => square(fun(Function::Sech, u)),
+        Function::Coth => neg(square(fun(Function::Csch, u))),
+        Function::Sech => neg(mult(fun(Function::Sech, u.clone()), fun(Function::Tanh, u))),
+        Function::Csch => neg(mult(fun(Function::Csch, u.clone()), fun(Function::Coth, u))),
+        Function::Log | Function::Ln => div(constant(1.0), u),
+        Function::Asin => div(
+            constant(1.0),
+            fun(Function::Sqrt, sub(constant(1.0), square(u))),
+        ),
+        Function::Acos => neg(div(
+            constant(1.0),
+            fun(Function::Sqrt, sub(constant(1.0), square(u))),
+        )),
+        Function::Atan => div(constant(1.0), add(constant(1.0), square(u))),
+        Function::Asinh => div(
+            constant(1.0),
+            fun(Function::Sqrt, add(square(u), constant(1.0))),
+        ),
+        // Written as a product of square roots so it is correct on the
+        // principal branch, including left of -1
+        Function::Acosh => div(
+            constant(1.0),
+            mult(
+                fun(Function::Sqrt, sub(u.clone(), constant(1.0))),
+                fun(Function::Sqrt, add(u, constant(1.0))),
+            ),
+        ),
+        Function::Atanh => div(constant(1.0), sub(constant(1.0), square(u))),
+        Function::Gamma => mult(fun(Function::Gamma, u.clone()), fun(Function::Digamma, u)),
+        Function::Re
+        | Function::Im
+        | Function::Abs
+        | Function::Arg
+        | Function::Conj
+        | Function::Digamma
+        | Function::Zeta => constant(f64::NAN),
+        Function::Pow | Function::Atan2 => panic!("Error in differentiation ({f} takes two arguments), please report this to program maintainer"),
+    }
+}
+
+/// Derivative of a function of two arguments `a` and `b`
+fn fun2_derivative(f: Function, a: &Node, b: &Node) -> Node {
+    match f {
+        Function::Pow => pow_derivative(a, b),
+        Function::Log => div(fun(Function::Ln, a.clone()), fun(Function::Ln, b.clone())).derivative(),
+        // d/dz atan2(y, x) = (x y' - y x') / (x^2 + y^2)
+        Function::Atan2 => {
+            let (y, x) = (a, b);
+            div(
+                sub(mult(x.clone(), y.derivative()), mult(y.clone(), x.derivative())),
+                add(square(x.clone()), square(y.clone())),
+            )
+        }
+        _ => panic!("Error in differentiation ({f} takes one argument), please report this to program maintainer"),
+    }
+}
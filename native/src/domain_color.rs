@@ -1,108 +1,857 @@
-use crate::parser;
-use hsluv;
+use crate::bmp::{self, BmpOptions};
+use crate::bytecode::Program;
+use crate::color::{ColorScheme, ModulusMap, Rgb, Scheme};
+use crate::context::{Context, UnboundParameter};
+use crate::image::{self, Image};
+use crate::job::RenderJob;
+use crate::parser::{self, ParseError};
+pub use crate::viewport::Viewport;
 pub use num::complex::Complex;
-use num::complex::ComplexFloat;
+use rayon::prelude::*;
 use std::f64::consts::PI as PI64;
+use std::fmt;
+use std::ops::Range;
 
-struct Rgb {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
+/// Value of a named parameter of the function
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub re: f64,
+    pub im: f64,
 }
 
-#[allow(dead_code)]
-impl Rgb {
-    fn from_linear(r: f64, g: f64, b: f64) -> Self {
-        Rgb {
-            r: (r * 255.0) as u8,
-            g: (g * 255.0) as u8,
-            b: (b * 255.0) as u8,
+#[derive(Clone, Debug)]
+pub struct DCOptions {
+    pub viewport: Viewport,
+    /// Grows the viewport to the aspect ratio of the image, so that pixels are
+    /// square and the plot is not stretched
+    pub preserve_aspect: bool,
+    pub scheme: Scheme,
+    /// How the modulus of the function is shown as lightness
+    pub modulus_map: ModulusMap,
+    /// Ratio between the moduli of successive modulus contours, e.g. `2.0`
+    /// draws lines where `|f|` is a power of two. `None` disables them.
+    pub modulus_contours: Option<f64>,
+    /// Number of phase contours per full turn, spaced `2π / n` apart. `0`
+    /// disables them.
+    pub phase_contours: u32,
+    /// Draws lines where `Re f` or `Im f` is a multiple of this spacing, the
+    /// preimage of a Cartesian grid in the image plane. `None` disables them.
+    pub grid_spacing: Option<f64>,
+    /// Width of contour lines in pixels
+    pub line_width: f64,
+    /// Values of the parameters used in the function
+    pub params: Vec<Param>,
+    /// Plots the derivative of this order instead of the function itself, at
+    /// most [`MAX_DERIVATIVE`]
+    pub derivative: u32,
+    /// Samples per pixel along each axis, taken on an `n` by `n` grid and
+    /// averaged in linear light with the contour lines drawn over each, so the
+    /// lines are smoothed as well. `1` samples the center of each pixel only.
+    pub supersampling: u32,
+    /// Moves each sample to a random point of its cell of the grid (stratified
+    /// sampling), trading regular aliasing patterns for noise. The offsets
+    /// depend only on the pixel, so renders are reproducible.
+    pub jitter: bool,
+    /// Supersamples only the pixels whose phase or modulus differs a lot from
+    /// a neighbor's, splitting them recursively into quadrants while the
+    /// samples keep differing, down to `supersampling` samples per side
+    /// (rounded down to a power of two). Jitter is not applied.
+    pub adaptive: bool,
+    /// Color of points where the function is zero
+    pub zero_color: Rgb,
+    /// Color of points where the function is infinite, such as poles
+    pub infinity_color: Rgb,
+    /// Color of points where the function is undefined (NaN), such as `0 / 0`
+    pub nan_color: Rgb,
+}
+
+impl Default for DCOptions {
+    fn default() -> Self {
+        DCOptions {
+            viewport: Viewport::default(),
+            preserve_aspect: true,
+            scheme: Scheme::default(),
+            modulus_map: ModulusMap::default(),
+            modulus_contours: None,
+            phase_contours: 0,
+            grid_spacing: None,
+            line_width: 1.0,
+            params: Vec::new(),
+            derivative: 0,
+            supersampling: 1,
+            jitter: false,
+            adaptive: false,
+            zero_color: Rgb { r: 0, g: 0, b: 0 },
+            infinity_color: Rgb {
+                r: 255,
+                g: 255,
+                b: 255,
+            },
+            nan_color: Rgb {
+                r: 128,
+                g: 128,
+                b: 128,
+            },
         }
     }
 }
 
-pub struct DCOptions {
-    pub xmin: f64,
-    pub xmax: f64,
-    pub ymin: f64,
-    pub ymax: f64,
+/// Reasons a domain coloring cannot be rendered
+#[derive(Clone, Debug, PartialEq)]
+pub enum RenderError {
+    /// The function string is not a valid expression
+    Parse(ParseError),
+    /// The function uses a parameter that has no value in `DCOptions::params`
+    UnboundParameter(UnboundParameter),
+    /// The image is empty, or too large to be stored as a BMP
+    InvalidDimensions { width: usize, height: usize },
+    /// The x range is empty, inverted, or not finite
+    InvalidXRange { xmin: f64, xmax: f64 },
+    /// The y range is empty, inverted, or not finite
+    InvalidYRange { ymin: f64, ymax: f64 },
+    /// A contour spacing or the line width is out of range
+    InvalidContours,
+    /// The number of samples per pixel side is not between 1 and [`MAX_SUPERSAMPLING`]
+    InvalidSupersampling(u32),
+    /// The base or exponent of the modulus mapping is out of range
+    InvalidModulusMap,
+    /// The derivative order is greater than [`MAX_DERIVATIVE`]
+    InvalidDerivative(u32),
+    /// The image could not be encoded
+    Encoding(String),
+    /// The pixel buffer passed to [`render_into`] has the wrong size
+    InvalidBufferSize { expected: usize, found: usize },
+    /// The render was cancelled through its [`RenderJob`]
+    Cancelled,
+    /// A tile request covers more than [`MAX_TILES`](crate::tiles::MAX_TILES) tiles
+    TooManyTiles { count: usize },
+}
+
+impl From<ParseError> for RenderError {
+    fn from(err: ParseError) -> Self {
+        RenderError::Parse(err)
+    }
+}
+
+impl From<UnboundParameter> for RenderError {
+    fn from(err: UnboundParameter) -> Self {
+        RenderError::UnboundParameter(err)
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Parse(err) => write!(f, "{err}"),
+            RenderError::UnboundParameter(err) => write!(f, "{err}"),
+            RenderError::InvalidDimensions { width, height } => {
+                write!(f, "Invalid image dimensions {width}x{height}")
+            }
+            RenderError::InvalidXRange { xmin, xmax } => {
+                write!(
+                    f,
+                    "Invalid x range: xmin ({xmin}) must be less than xmax ({xmax})"
+                )
+            }
+            RenderError::InvalidYRange { ymin, ymax } => {
+                write!(
+                    f,
+                    "Invalid y range: ymin ({ymin}) must be less than ymax ({ymax})"
+                )
+            }
+            RenderError::InvalidContours => write!(
+                f,
+                "Invalid contours: the modulus ratio must be greater than 1, and the grid spacing and line width positive"
+            ),
+            RenderError::InvalidSupersampling(n) => write!(
+                f,
+                "Invalid supersampling: {n} samples per pixel side, expected 1 to {MAX_SUPERSAMPLING}"
+            ),
+            RenderError::InvalidModulusMap => write!(
+                f,
+                "Invalid modulus mapping: the base must be greater than 1 and the exponent positive"
+            ),
+            RenderError::InvalidDerivative(n) => write!(
+                f,
+                "Invalid derivative: order {n}, expected at most {MAX_DERIVATIVE}"
+            ),
+            RenderError::Encoding(err) => write!(f, "Could not encode image: {err}"),
+            RenderError::InvalidBufferSize { expected, found } => write!(
+                f,
+                "Invalid pixel buffer size: expected {expected} bytes, found {found}"
+            ),
+            RenderError::Cancelled => write!(f, "Render cancelled"),
+            RenderError::TooManyTiles { count } => {
+                write!(f, "Too many tiles requested ({count})")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+/// Whether `min..max` is a non-empty finite range
+fn valid_range(min: f64, max: f64) -> bool {
+    min.is_finite() && max.is_finite() && min < max
+}
+
+pub(crate) fn validate(
+    width: usize,
+    height: usize,
+    options: &DCOptions,
+) -> Result<(), RenderError> {
+    // Any image that can be rendered can be stored as a BMP, even with alpha
+    let bmp_options = BmpOptions {
+        top_down: true,
+        alpha: true,
+    };
+    let fits = bmp::file_size(width, height, bmp_options).is_some();
+    if width == 0 || height == 0 || !fits {
+        return Err(RenderError::InvalidDimensions { width, height });
+    }
+    let Viewport {
+        xmin,
+        xmax,
+        ymin,
+        ymax,
+    } = options.viewport;
+    if !valid_range(xmin, xmax) {
+        return Err(RenderError::InvalidXRange { xmin, xmax });
+    }
+    if !valid_range(ymin, ymax) {
+        return Err(RenderError::InvalidYRange { ymin, ymax });
+    }
+    let valid_contours = options
+        .modulus_contours
+        .is_none_or(|r| r.is_finite() && r > 1.0)
+        && options
+            .grid_spacing
+            .is_none_or(|s| s.is_finite() && s > 0.0)
+        && options.line_width.is_finite()
+        && options.line_width > 0.0;
+    if !valid_contours {
+        return Err(RenderError::InvalidContours);
+    }
+    if !(1..=MAX_SUPERSAMPLING).contains(&options.supersampling) {
+        return Err(RenderError::InvalidSupersampling(options.supersampling));
+    }
+    if !options.modulus_map.is_valid() {
+        return Err(RenderError::InvalidModulusMap);
+    }
+    if options.derivative > MAX_DERIVATIVE {
+        return Err(RenderError::InvalidDerivative(options.derivative));
+    }
+    Ok(())
 }
 
 fn good_arg(z: Complex<f64>) -> f64 {
     if z.arg() >= 0.0 {
-        (z.arg() as f64) / PI64 * 180.0
+        z.arg() / PI64 * 180.0
     } else {
-        z.arg() as f64 * 180.0 / PI64 + 360.0
+        z.arg() * 180.0 / PI64 + 360.0
     }
 }
 
-fn color_bytes(fun_val: Complex<f64>) -> Rgb {
+fn color_bytes(fun_val: Complex<f64>, options: &DCOptions) -> Rgb {
     //! returns RGB color corresponding to function value
 
-    let lightness: f64 = (100.0 * fun_val.abs() / (fun_val.abs() + 1.0)) as f64;
-    let saturation: f64 = 100.0;
+    if fun_val.is_nan() {
+        return options.nan_color;
+    } else if fun_val.is_infinite() {
+        return options.infinity_color;
+    } else if fun_val == Complex::new(0.0, 0.0) {
+        return options.zero_color;
+    }
     let hue: f64 = good_arg(fun_val);
-    let linear = hsluv::hpluv_to_rgb(hue, saturation, lightness);
-    Rgb::from_linear(linear.0, linear.1, linear.2)
-}
-
-pub fn color_bmp(width: usize, height: usize, fun_str: &str, options: DCOptions) -> Vec<u8> {
-    // Parse the function
-    let function = parser::parse_to_fn(fun_str).unwrap();
-
-    let mut header = vec![
-        b'B', b'M', 0, 0, 0, 0, // File size, to be updated later
-        0, 0, 0, 0, // Reserved, 0
-        0x36, 0, 0, 0, // Pixel buffer offset
-        0x28, 0, 0, 0, // (DIB) Header size
-    ];
-    header.extend_from_slice(&(width as u32).to_le_bytes());
-    header.extend_from_slice(&(height as u32).to_le_bytes());
-    header.extend_from_slice(&[
-        1, 0, // 1 Color plane
-        24, 0, // 24 bits (3 bytes) per pixel
-        0, 0, 0, 0, // No compression
-        0, // Image size, ignored for uncompressed so we set to 0
-        0, 0, 0, 0, // Horizontal pixels per meter, irrelevant
-        0, 0, 0, 0, // Vertical pixels per meter, irrelevant
-        0, 0, 0, 0, // Palette size, irrelevant
-        0, 0, 0, 0, // Number of "important" colors
-    ]);
-
-    // BMP Header size
-    let header_size = header.len();
-    let buffer_size = 3 * height * (width + 1); // +1 for row padding?
-
-    // Update header with file size
-    let file_size = ((header_size + buffer_size) as u32).to_le_bytes();
-    header[2] = file_size[0];
-    header[3] = file_size[1];
-    header[4] = file_size[2];
-    header[5] = file_size[3];
-
-    // BMP buffer
-    let mut buffer: Vec<u8> = vec![0; header_size + buffer_size];
-
-    // Set the header
-    buffer[0..header_size].copy_from_slice(&header);
-
-    // Set the pixels: Domain Coloring
-    let mut x: f64;
-    let mut y: f64;
-    let x_step: f64 = (options.xmax - options.xmin) / (width as f64);
-    let y_step: f64 = (options.ymax - options.xmin) / (height as f64);
-    let mut rgb: Rgb;
-
-    for y_px in 0..=(height - 1) {
-        for x_px in 0..=(width - 1) {
-            x = options.xmin + x_px as f64 * x_step;
-            y = options.ymin + y_px as f64 * y_step;
-            rgb = color_bytes(function(Complex::new(x, y)));
-            // Consider get_unchecked with a debug assertion
-            buffer[header_size + 3 + y_px * 3 * width + 3 * x_px] = rgb.b;
-            buffer[header_size + 3 + y_px * 3 * width + 3 * x_px + 1] = rgb.g;
-            buffer[header_size + 3 + y_px * 3 * width + 3 * x_px + 2] = rgb.r;
+    // The modulus of finite values can still overflow, which the mapping
+    // treats as infinite
+    let lightness: f64 = options.modulus_map.lightness(fun_val.norm(), hue);
+    options.scheme.color(hue, lightness)
+}
+
+/// Most samples per pixel side
+pub const MAX_SUPERSAMPLING: u32 = 16;
+
+/// Highest derivative order. Through the product, quotient and chain rules
+/// each derivative can multiply the size of the expression several times.
+pub const MAX_DERIVATIVE: u32 = 5;
+
+/// Mixes the bits of `v`, as in SplitMix64
+fn hash(mut v: u64) -> u64 {
+    v = (v ^ (v >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    v = (v ^ (v >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    v ^ (v >> 31)
+}
+
+/// Pseudo-random number in `[0, 1)` determined by `seed`
+fn unit_random(seed: u64) -> f64 {
+    (hash(seed) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Largest change in phase, in radians, and in the logarithm of the modulus
+/// per pixel of distance between samples before adaptive sampling refines them
+const ADAPTIVE_PHASE_TOLERANCE: f64 = 0.05;
+const ADAPTIVE_MODULUS_TOLERANCE: f64 = 0.05;
+
+/// Whether the colors of two function values sampled `distance` pixels apart
+/// vary fast enough to differ noticeably within a pixel
+fn large_change(a: Complex<f64>, b: Complex<f64>, distance: f64) -> bool {
+    if !(a.is_finite() && b.is_finite()) {
+        return a.is_finite() != b.is_finite();
+    }
+    let ratio = b / a;
+    if !(ratio.is_finite() && ratio != Complex::new(0.0, 0.0)) {
+        // One of them is zero
+        return a != b;
+    }
+    ratio.arg().abs() > ADAPTIVE_PHASE_TOLERANCE * distance
+        || ratio.norm().ln().abs() > ADAPTIVE_MODULUS_TOLERANCE * distance
+}
+
+/// Opacity of contour lines drawn over the domain coloring
+const LINE_OPACITY: f64 = 0.6;
+
+impl DCOptions {
+    /// The viewport actually shown in a `width` by `height` image
+    pub fn effective_viewport(&self, width: usize, height: usize) -> Viewport {
+        if self.preserve_aspect {
+            self.viewport.fit_aspect(width, height)
+        } else {
+            self.viewport
+        }
+    }
+
+    /// Evaluation context binding the parameters to their values
+    fn context(&self) -> Context {
+        self.params
+            .iter()
+            .map(|param| (param.name.as_str(), Complex::new(param.re, param.im)))
+            .collect()
+    }
+
+    fn has_contours(&self) -> bool {
+        self.modulus_contours.is_some() || self.phase_contours > 0 || self.grid_spacing.is_some()
+    }
+}
+
+/// Fraction of a pixel covered by the level lines of `q`, which lie at its
+/// integer values. `dq_dx` and `dq_dy` are the changes in `q` across one pixel,
+/// so the distance to the nearest line can be measured in pixels and the line
+/// edges anti-aliased.
+fn line_coverage(q: f64, dq_dx: f64, dq_dy: f64, line_width: f64) -> f64 {
+    let gradient = dq_dx.hypot(dq_dy);
+    if !(q.is_finite() && gradient.is_finite() && gradient > 0.0) {
+        return 0.0;
+    }
+    let dist_px = (q - q.round()).abs() / gradient;
+    (0.5 * line_width + 0.5 - dist_px).clamp(0.0, 1.0)
+}
+
+/// Coverage of all enabled contours at a pixel, given the function value there
+/// (`val`) and one pixel to the right (`val_dx`) and up (`val_dy`)
+fn contour_coverage(
+    val: Complex<f64>,
+    val_dx: Complex<f64>,
+    val_dy: Complex<f64>,
+    options: &DCOptions,
+) -> f64 {
+    let width = options.line_width;
+    let mut coverage: f64 = 0.0;
+    if let Some(ratio) = options.modulus_contours {
+        // Levels of log|f|, differences taken as ratios so they stay accurate near zeros
+        let scale = ratio.ln();
+        coverage = coverage.max(line_coverage(
+            val.norm().ln() / scale,
+            (val_dx / val).norm().ln() / scale,
+            (val_dy / val).norm().ln() / scale,
+            width,
+        ));
+    }
+    if options.phase_contours > 0 {
+        // Differences of arg f taken through the ratio avoid jumps at the branch cut
+        let scale = options.phase_contours as f64 / (2.0 * PI64);
+        coverage = coverage.max(line_coverage(
+            val.arg() * scale,
+            (val_dx / val).arg() * scale,
+            (val_dy / val).arg() * scale,
+            width,
+        ));
+    }
+    if let Some(spacing) = options.grid_spacing {
+        let (d_dx, d_dy) = ((val_dx - val) / spacing, (val_dy - val) / spacing);
+        coverage = coverage.max(line_coverage(val.re / spacing, d_dx.re, d_dy.re, width));
+        coverage = coverage.max(line_coverage(val.im / spacing, d_dx.im, d_dy.im, width));
+    }
+    coverage
+}
+
+/// Darkens `rgb` where a contour line covers the pixel
+fn draw_line(rgb: Rgb, coverage: f64) -> Rgb {
+    let keep = 1.0 - LINE_OPACITY * coverage;
+    Rgb {
+        r: (rgb.r as f64 * keep) as u8,
+        g: (rgb.g as f64 * keep) as u8,
+        b: (rgb.b as f64 * keep) as u8,
+    }
+}
+
+/// Colors each pixel of a `width` by `height` image by the value of the
+/// function `fun_str` at the corresponding point of the plane
+pub fn render(
+    width: usize,
+    height: usize,
+    fun_str: &str,
+    options: &DCOptions,
+) -> Result<Image, RenderError> {
+    let mut image = Image::new(width, height);
+    render_into(width, height, fun_str, options, &mut image.pixels)?;
+    Ok(image)
+}
+
+/// Renders into a caller-provided buffer of `4 * width * height` bytes, laid
+/// out like [`Image::pixels`]: RGBA8 rows from the top, with a stride of
+/// `4 * width` bytes and no padding. Every byte is overwritten, the alpha
+/// channel with 255.
+pub fn render_into(
+    width: usize,
+    height: usize,
+    fun_str: &str,
+    options: &DCOptions,
+    pixels: &mut [u8],
+) -> Result<(), RenderError> {
+    validate(width, height, options)?;
+    if pixels.len() != 4 * width * height {
+        return Err(RenderError::InvalidBufferSize {
+            expected: 4 * width * height,
+            found: pixels.len(),
+        });
+    }
+    let plotter = Plotter::new(fun_str, options)?;
+    let viewport = options.effective_viewport(width, height);
+    plotter.color_region(
+        &viewport,
+        width,
+        height,
+        pixels,
+        0..width,
+        0..height,
+        &RenderJob::default(),
+    )
+}
+
+/// A function compiled with its parameters bound, ready to color pixels
+pub(crate) struct Plotter {
+    program: Program,
+    params: Vec<Complex<f64>>,
+    options: DCOptions,
+}
+
+impl Plotter {
+    /// Parses and compiles `fun_str`. The dimensions and viewport are not
+    /// checked, see [`validate`].
+    pub(crate) fn new(fun_str: &str, options: &DCOptions) -> Result<Self, RenderError> {
+        // Parse the function
+        let mut node = parser::parse(fun_str)?.simplify();
+        // Simplifying after each step keeps the trees from growing needlessly
+        for _ in 0..options.derivative {
+            node = node.derivative().simplify();
+        }
+        let program = Program::compile(&node);
+        let params = program.bind(&options.context())?;
+        Ok(Plotter {
+            program,
+            params,
+            options: options.clone(),
+        })
+    }
+
+    /// Colors the pixels in columns `cols` of rows `rows` of a `width` by
+    /// `height` image showing `viewport`, leaving the others untouched.
+    ///
+    /// Each colored row is counted towards the progress of `job`, and the
+    /// render stops early if it is cancelled.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn color_region(
+        &self,
+        viewport: &Viewport,
+        width: usize,
+        height: usize,
+        pixels: &mut [u8],
+        cols: Range<usize>,
+        rows: Range<usize>,
+        job: &RenderJob,
+    ) -> Result<(), RenderError> {
+        let (program, params, options) = (&self.program, &self.params, &self.options);
+        let center =
+            |x_px: f64, y_px: f64| viewport.pixel_to_complex(x_px + 0.5, y_px + 0.5, width, height);
+
+        // Adaptive sampling compares each pixel with its eight neighbors, so
+        // their values at the pixel centers are computed first. The grid has a
+        // border of one pixel around the region, so that regions are refined
+        // the same whether they are colored alone or as part of a full image.
+        let grid_width = cols.len() + 2;
+        let centers = if options.adaptive && options.supersampling > 1 {
+            let mut centers = vec![Complex::new(0.0, 0.0); grid_width * (rows.len() + 2)];
+            centers
+                .par_chunks_mut(grid_width)
+                .enumerate()
+                .try_for_each_init(Vec::new, |regs, (grid_row, vals)| {
+                    job.check()?;
+                    let y_px = rows.start as f64 + grid_row as f64 - 1.0;
+                    for (grid_col, val) in vals.iter_mut().enumerate() {
+                        let x_px = cols.start as f64 + grid_col as f64 - 1.0;
+                        *val = program.eval_with(center(x_px, y_px), params, regs);
+                    }
+                    Ok::<(), RenderError>(())
+                })?;
+            Some(centers)
+        } else {
+            None
+        };
+
+        // Each row is colored independently, so rows are split across the rayon pool
+        pixels[4 * width * rows.start..4 * width * rows.end]
+            .par_chunks_mut(4 * width)
+            .enumerate()
+            .try_for_each_init(Vec::new, |regs, (row_idx, row)| {
+                let y_px = rows.start + row_idx;
+                let row = &mut row[4 * cols.start..4 * cols.end];
+                for (col_idx, pixel) in row.chunks_exact_mut(4).enumerate() {
+                    job.check()?;
+                    let x_px = cols.start + col_idx;
+                    // Sample at the center of the pixel
+                    let z = center(x_px as f64, y_px as f64);
+                    let rgb = match &centers {
+                        Some(centers) => {
+                            let at = |col: usize, row: usize| centers[row * grid_width + col];
+                            let (grid_col, grid_row) = (col_idx + 1, row_idx + 1);
+                            let val = at(grid_col, grid_row);
+                            let refine = (grid_row - 1..=grid_row + 1).any(|row| {
+                                (grid_col - 1..=grid_col + 1).any(|col| {
+                                    let distance = (col.abs_diff(grid_col) as f64)
+                                        .hypot(row.abs_diff(grid_row) as f64);
+                                    large_change(val, at(col, row), distance)
+                                })
+                            });
+                            if refine {
+                                let linear = self.refine(
+                                    viewport,
+                                    (x_px as f64, y_px as f64),
+                                    1.0,
+                                    val,
+                                    (width, height),
+                                    1,
+                                    regs,
+                                );
+                                Rgb::from_linear_light(linear)
+                            } else {
+                                self.sample_color(viewport, (width, height), z, val, regs)
+                            }
+                        }
+                        None if options.supersampling > 1 => {
+                            self.supersample(viewport, x_px, y_px, width, height, regs)
+                        }
+                        None => {
+                            let val = program.eval_with(z, params, regs);
+                            self.sample_color(viewport, (width, height), z, val, regs)
+                        }
+                    };
+                    pixel[0] = rgb.r;
+                    pixel[1] = rgb.g;
+                    pixel[2] = rgb.b;
+                    pixel[3] = 255;
+                }
+                job.add_done(cols.len());
+                Ok(())
+            })
+    }
+
+    /// Linear-light color of the square cell of side `size` pixels with top
+    /// left corner `corner`, in pixel coordinates, where the function takes
+    /// the value `val` at the center.
+    ///
+    /// The centers of the four quadrants of the cell are sampled, and while
+    /// they differ a lot from each other or from the center each quadrant is
+    /// refined in turn, down to `depth` levels such that `2^depth` does not
+    /// exceed `supersampling`.
+    #[allow(clippy::too_many_arguments)]
+    fn refine(
+        &self,
+        viewport: &Viewport,
+        corner: (f64, f64),
+        size: f64,
+        val: Complex<f64>,
+        (width, height): (usize, usize),
+        depth: u32,
+        regs: &mut Vec<Complex<f64>>,
+    ) -> [f64; 3] {
+        let half = 0.5 * size;
+        let quadrants = [(0.0, 0.0), (half, 0.0), (0.0, half), (half, half)]
+            .map(|(dx, dy)| (corner.0 + dx, corner.1 + dy));
+        let points = quadrants
+            .map(|(x, y)| viewport.pixel_to_complex(x + 0.5 * half, y + 0.5 * half, width, height));
+        let vals = points.map(|z| self.program.eval_with(z, &self.params, regs));
+        let max_depth = self.options.supersampling.ilog2();
+        // Quadrant centers are `half` apart, or `half * √2` diagonally, and
+        // `half / √2` from the center of the cell
+        let diagonal = std::f64::consts::SQRT_2;
+        let varies = (0..4).any(|i| {
+            large_change(val, vals[i], half / diagonal)
+                || (i + 1..4).any(|j| {
+                    let distance = if i + j == 3 { half * diagonal } else { half };
+                    large_change(vals[i], vals[j], distance)
+                })
+        });
+
+        let mut sum = [0.0; 3];
+        for ((quadrant, z), quadrant_val) in quadrants.into_iter().zip(points).zip(vals) {
+            let linear = if varies && depth < max_depth {
+                self.refine(
+                    viewport,
+                    quadrant,
+                    half,
+                    quadrant_val,
+                    (width, height),
+                    depth + 1,
+                    regs,
+                )
+            } else {
+                self.sample_color(viewport, (width, height), z, quadrant_val, regs)
+                    .to_linear_light()
+            };
+            for (sum, c) in sum.iter_mut().zip(linear) {
+                *sum += 0.25 * c;
+            }
+        }
+        sum
+    }
+
+    /// Color of the sample at `z`, where the function takes the value `val`,
+    /// with the contour lines covering it drawn over
+    fn sample_color(
+        &self,
+        viewport: &Viewport,
+        (width, height): (usize, usize),
+        z: Complex<f64>,
+        val: Complex<f64>,
+        regs: &mut Vec<Complex<f64>>,
+    ) -> Rgb {
+        let rgb = color_bytes(val, &self.options);
+        if !self.options.has_contours() {
+            return rgb;
         }
+        // Contour widths are in pixels, so the gradient is taken one pixel away
+        let x_step: f64 = viewport.width() / (width as f64);
+        let y_step: f64 = viewport.height() / (height as f64);
+        let val_dx = self.program.eval_with(z + x_step, &self.params, regs);
+        let val_dy = self
+            .program
+            .eval_with(z + Complex::new(0.0, y_step), &self.params, regs);
+        draw_line(rgb, contour_coverage(val, val_dx, val_dy, &self.options))
+    }
+
+    /// Color of the pixel in column `x_px` of row `y_px`, averaged in linear
+    /// light over a grid of samples, contour lines included
+    fn supersample(
+        &self,
+        viewport: &Viewport,
+        x_px: usize,
+        y_px: usize,
+        width: usize,
+        height: usize,
+        regs: &mut Vec<Complex<f64>>,
+    ) -> Rgb {
+        let n = self.options.supersampling as usize;
+        let seed = ((y_px as u64) << 32 | x_px as u64) * (n * n) as u64;
+        let mut sum = [0.0; 3];
+        for sample in 0..n * n {
+            let (mut dx, mut dy) = (0.5, 0.5);
+            if self.options.jitter {
+                let seed = 2 * (seed + sample as u64);
+                (dx, dy) = (unit_random(seed), unit_random(seed + 1));
+            }
+            let z = viewport.pixel_to_complex(
+                x_px as f64 + ((sample % n) as f64 + dx) / n as f64,
+                y_px as f64 + ((sample / n) as f64 + dy) / n as f64,
+                width,
+                height,
+            );
+            let val = self.program.eval_with(z, &self.params, regs);
+            let linear = self
+                .sample_color(viewport, (width, height), z, val, regs)
+                .to_linear_light();
+            for (sum, c) in sum.iter_mut().zip(linear) {
+                *sum += c;
+            }
+        }
+        Rgb::from_linear_light(sum.map(|c| c / (n * n) as f64))
+    }
+}
+
+/// Renders the function as a 24-bit BMP with rows stored from the bottom up
+pub fn color_bmp(
+    width: usize,
+    height: usize,
+    fun_str: &str,
+    options: DCOptions,
+) -> Result<Vec<u8>, RenderError> {
+    let image = render(width, height, fun_str, &options)?;
+    Ok(bmp::encode(&image, BmpOptions::default()))
+}
+
+/// Renders the function as a PNG. With `metadata`, the expression, viewport,
+/// parameters and derivative order are stored in text chunks, to be read back
+/// with [`PngMetadata::from_png`].
+pub fn color_png(
+    width: usize,
+    height: usize,
+    fun_str: &str,
+    options: DCOptions,
+    metadata: bool,
+) -> Result<Vec<u8>, RenderError> {
+    let image = render(width, height, fun_str, &options)?;
+    let text = if metadata {
+        PngMetadata::new(width, height, fun_str, &options)?.to_text()
+    } else {
+        Vec::new()
+    };
+    image
+        .to_png(&text)
+        .map_err(|err| RenderError::Encoding(err.to_string()))
+}
+
+/// Everything needed to render a PNG written by [`color_png`] again
+#[derive(Clone, Debug, PartialEq)]
+pub struct PngMetadata {
+    pub expression: String,
+    /// The viewport shown in the image, after any aspect ratio correction
+    pub viewport: Viewport,
+    /// Values of the parameters used in the expression
+    pub params: Vec<Param>,
+    pub derivative: u32,
+}
+
+/// Reasons the metadata of a PNG cannot be read
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataError {
+    /// The file is not a valid PNG
+    Decoding(String),
+    /// The text chunk with this keyword is missing
+    Missing(&'static str),
+    /// The text chunk with this keyword could not be parsed
+    Invalid(&'static str),
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataError::Decoding(err) => write!(f, "Invalid PNG: {err}"),
+            MetadataError::Missing(keyword) => write!(f, "Missing {keyword} in PNG metadata"),
+            MetadataError::Invalid(keyword) => write!(f, "Invalid {keyword} in PNG metadata"),
+        }
+    }
+}
+
+impl std::error::Error for MetadataError {}
+
+const EXPRESSION_KEY: &str = "Expression";
+const VIEWPORT_KEY: &str = "Viewport";
+const PARAMETERS_KEY: &str = "Parameters";
+const DERIVATIVE_KEY: &str = "Derivative";
+
+impl PngMetadata {
+    fn new(
+        width: usize,
+        height: usize,
+        fun_str: &str,
+        options: &DCOptions,
+    ) -> Result<Self, RenderError> {
+        // Only the parameters the expression uses, whose names are identifiers
+        let used = parser::parse_to_program(fun_str)?;
+        let params = options
+            .params
+            .iter()
+            .filter(|param| used.params().contains(&param.name))
+            .cloned()
+            .collect();
+        Ok(PngMetadata {
+            expression: fun_str.to_string(),
+            viewport: options.effective_viewport(width, height),
+            params,
+            derivative: options.derivative,
+        })
+    }
+
+    /// Text chunks recording the metadata. Numbers are written in full
+    /// precision, the viewport as `xmin xmax ymin ymax` and each parameter on
+    /// its own line as `name re im`.
+    fn to_text(&self) -> Vec<(String, String)> {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|param| format!("{} {} {}", param.name, param.re, param.im))
+            .collect();
+        [
+            (EXPRESSION_KEY, self.expression.clone()),
+            (
+                VIEWPORT_KEY,
+                format!(
+                    "{} {} {} {}",
+                    self.viewport.xmin, self.viewport.xmax, self.viewport.ymin, self.viewport.ymax
+                ),
+            ),
+            (PARAMETERS_KEY, params.join("\n")),
+            (DERIVATIVE_KEY, self.derivative.to_string()),
+        ]
+        .into_iter()
+        .map(|(keyword, text)| (keyword.to_string(), text))
+        .collect()
+    }
+
+    /// Reads the metadata written by [`color_png`]
+    pub fn from_png(png: &[u8]) -> Result<Self, MetadataError> {
+        let text = image::png_text(png).map_err(|err| MetadataError::Decoding(err.to_string()))?;
+        let get = |keyword: &'static str| {
+            text.iter()
+                .find(|(key, _)| key == keyword)
+                .map(|(_, text)| text.as_str())
+                .ok_or(MetadataError::Missing(keyword))
+        };
+
+        let expression = get(EXPRESSION_KEY)?.to_string();
+        let viewport: Vec<f64> = get(VIEWPORT_KEY)?
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| MetadataError::Invalid(VIEWPORT_KEY))?;
+        let [xmin, xmax, ymin, ymax] = viewport[..] else {
+            return Err(MetadataError::Invalid(VIEWPORT_KEY));
+        };
+        let params = get(PARAMETERS_KEY)?
+            .lines()
+            .map(
+                |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [name, re, im] => Some(Param {
+                        name: name.to_string(),
+                        re: re.parse().ok()?,
+                        im: im.parse().ok()?,
+                    }),
+                    _ => None,
+                },
+            )
+            .collect::<Option<_>>()
+            .ok_or(MetadataError::Invalid(PARAMETERS_KEY))?;
+        let derivative = get(DERIVATIVE_KEY)?
+            .parse()
+            .ok()
+            .filter(|&n| n <= MAX_DERIVATIVE)
+            .ok_or(MetadataError::Invalid(DERIVATIVE_KEY))?;
+        Ok(PngMetadata {
+            expression,
+            viewport: Viewport::new(xmin, xmax, ymin, ymax),
+            params,
+            derivative,
+        })
     }
-    buffer
 }
@@ -0,0 +1,66 @@
+use crate::color::Rgb;
+
+/// An 8-bit RGBA image, stored row by row from the top left corner
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    /// `4 * width * height` bytes, red first
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Opaque black image
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut pixels = vec![0; 4 * width * height];
+        pixels.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 255);
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Color of the pixel in column `x` of row `y`, counted from the top
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb {
+        let idx = 4 * (y * self.width + x);
+        Rgb {
+            r: self.pixels[idx],
+            g: self.pixels[idx + 1],
+            b: self.pixels[idx + 2],
+        }
+    }
+
+    /// Rows of pixels from top to bottom, `4 * width` bytes each
+    pub fn rows(&self) -> std::slice::ChunksExact<'_, u8> {
+        self.pixels.chunks_exact(4 * self.width)
+    }
+
+    /// Encodes the image as a PNG, adding a `tEXt` chunk for each
+    /// `(keyword, text)` pair. Keywords must be 1 to 79 Latin-1 characters, and
+    /// the text Latin-1.
+    pub fn to_png(&self, text: &[(String, String)]) -> Result<Vec<u8>, png::EncodingError> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        for (keyword, text) in text {
+            encoder.add_text_chunk(keyword.clone(), text.clone())?;
+        }
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(png)
+    }
+}
+
+/// The `(keyword, text)` pairs of the `tEXt` chunks before the image data of a PNG
+pub fn png_text(png: &[u8]) -> Result<Vec<(String, String)>, png::DecodingError> {
+    let reader = png::Decoder::new(png).read_info()?;
+    Ok(reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+        .collect())
+}
@@ -0,0 +1,176 @@
+use crate::domain_color::{self, DCOptions, Plotter, RenderError};
+use crate::image::Image;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Handle to a render that can be cancelled and reports its progress.
+///
+/// Clones share the same state, so one can be kept by the caller while
+/// another is used by the thread doing the rendering. The default job is
+/// never cancelled unless [`RenderJob::cancel`] is called on it.
+#[derive(Clone, Default)]
+pub struct RenderJob {
+    state: Arc<JobState>,
+}
+
+#[derive(Default)]
+struct JobState {
+    cancelled: AtomicBool,
+    /// Pixels colored so far and in total, over all passes
+    done: AtomicUsize,
+    total: AtomicUsize,
+    result: Mutex<Option<Result<Image, RenderError>>>,
+}
+
+impl RenderJob {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the render to stop. Pixel loops check this before every pixel,
+    /// and the render then fails with [`RenderError::Cancelled`].
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fraction of the pixels colored so far, from 0 to 1
+    pub fn progress(&self) -> f64 {
+        let total = self.state.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.0;
+        }
+        self.state.done.load(Ordering::Relaxed) as f64 / total as f64
+    }
+
+    /// Whether a job started with [`start`] has finished, successfully or not
+    pub fn is_finished(&self) -> bool {
+        self.state.result.lock().unwrap().is_some()
+    }
+
+    /// Takes the result of a job started with [`start`], or `None` if it is
+    /// still running or the result was already taken
+    pub fn take_result(&self) -> Option<Result<Image, RenderError>> {
+        self.state.result.lock().unwrap().take()
+    }
+
+    /// Adds `pixels` to the work the job has to do
+    pub(crate) fn add_work(&self, pixels: usize) {
+        self.state.total.fetch_add(pixels, Ordering::Relaxed);
+    }
+
+    /// Records that `pixels` more have been colored
+    pub(crate) fn add_done(&self, pixels: usize) {
+        self.state.done.fetch_add(pixels, Ordering::Relaxed);
+    }
+
+    /// Fails with [`RenderError::Cancelled`] once the job has been cancelled
+    pub(crate) fn check(&self) -> Result<(), RenderError> {
+        if self.is_cancelled() {
+            Err(RenderError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Starts rendering on a background thread, returning immediately. Poll the
+/// job for progress and take the image from it once it is finished.
+pub fn start(width: usize, height: usize, fun_str: String, options: DCOptions) -> RenderJob {
+    let job = RenderJob::new();
+    // Invalid dimensions could overflow the amount of work
+    if let Err(err) = domain_color::validate(width, height, &options) {
+        *job.state.result.lock().unwrap() = Some(Err(err));
+        return job;
+    }
+    job.add_work(width * height);
+    let worker = job.clone();
+    thread::spawn(move || {
+        let result = render(width, height, &fun_str, &options, &worker);
+        *worker.state.result.lock().unwrap() = Some(result);
+    });
+    job
+}
+
+/// Renders the whole image as part of `job`
+pub fn render(
+    width: usize,
+    height: usize,
+    fun_str: &str,
+    options: &DCOptions,
+    job: &RenderJob,
+) -> Result<Image, RenderError> {
+    domain_color::validate(width, height, options)?;
+    let plotter = Plotter::new(fun_str, options)?;
+    let viewport = options.effective_viewport(width, height);
+    let mut image = Image::new(width, height);
+    plotter.color_region(
+        &viewport,
+        width,
+        height,
+        &mut image.pixels,
+        0..width,
+        0..height,
+        job,
+    )?;
+    Ok(image)
+}
+
+/// Downscaling factors of the passes of a progressive render, coarsest first
+pub const PASSES: [usize; 4] = [8, 4, 2, 1];
+
+/// One pass of a progressive render: the whole viewport at a reduced resolution
+pub struct Frame {
+    pub image: Image,
+    /// The image is this many times smaller than the full resolution
+    pub scale: usize,
+}
+
+/// Renders the viewport at 1/8, 1/4, 1/2 and then full resolution, calling
+/// `on_frame` after each pass so that a coarse image can be shown right away.
+///
+/// The reduced images have `ceil(width / scale)` by `ceil(height / scale)`
+/// pixels and show the full-resolution viewport, so they can be scaled up to
+/// the final size. Passes that would not be smaller than the next one are
+/// skipped. Cancelling the job stops the render between or during passes.
+pub fn render_progressive(
+    width: usize,
+    height: usize,
+    fun_str: &str,
+    options: &DCOptions,
+    job: &RenderJob,
+    mut on_frame: impl FnMut(Frame),
+) -> Result<(), RenderError> {
+    domain_color::validate(width, height, options)?;
+    let plotter = Plotter::new(fun_str, options)?;
+    let viewport = options.effective_viewport(width, height);
+
+    let mut passes: Vec<(usize, usize, usize)> = Vec::new();
+    for scale in PASSES {
+        let size = (width.div_ceil(scale), height.div_ceil(scale));
+        // A finer pass of the same size replaces a coarser one
+        passes.retain(|&(_, w, h)| (w, h) != size);
+        passes.push((scale, size.0, size.1));
+    }
+    job.add_work(passes.iter().map(|(_, w, h)| w * h).sum());
+
+    for (scale, pass_width, pass_height) in passes {
+        job.check()?;
+        let mut image = Image::new(pass_width, pass_height);
+        plotter.color_region(
+            &viewport,
+            pass_width,
+            pass_height,
+            &mut image.pixels,
+            0..pass_width,
+            0..pass_height,
+            job,
+        )?;
+        on_frame(Frame { image, scale });
+    }
+    Ok(())
+}
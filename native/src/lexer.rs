@@ -1,15 +1,18 @@
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::iter::Peekable;
-use std::str::Chars;
+use std::str::CharIndices;
 use std::str::FromStr;
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LexError {
     DoubleDecimal,
+    InvalidNumber,
     InvalidCharacter,
+    UnknownIdentifier,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Function {
     Sqrt,
     Exp,
@@ -27,13 +30,31 @@ pub enum Function {
     Csch,
     Re,
     Im,
+    Pow,
+    Log,
+    Atan2,
+    Ln,
+    Asin,
+    Acos,
+    Atan,
+    Asinh,
+    Acosh,
+    Atanh,
+    Abs,
+    Arg,
+    Conj,
+    Gamma,
+    Digamma,
+    Zeta,
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Token {
     Float(f64),
     ComplexI,
     VarZ,
+    /// Name of a parameter. The name itself is the source text of the token's span.
+    Ident,
     Fun(Function),
     Add,
     Sub,
@@ -42,83 +63,162 @@ pub enum Token {
     Pow,
     LParen,
     RParen,
+    Comma,
+    Assign,
+    Semicolon,
     Error(LexError),
 }
 
-pub type Lexer<'a> = Peekable<LexerBaseIter<'a>>;
+/// Byte range of a token in the source string
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
 
-pub fn new_lexer(buffer: &str) -> Lexer {
-    LexerBaseIter::new(buffer).peekable()
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
 }
 
+/// Token stream consumed by the parser. Tracks the length of the source so
+/// that errors at the end of the input can still be located.
+pub struct Lexer<'a> {
+    tokens: Peekable<LexerBaseIter<'a>>,
+    source: &'a str,
+}
+
+pub fn new_lexer(buffer: &str) -> Lexer<'_> {
+    Lexer {
+        tokens: LexerBaseIter::new(buffer).peekable(),
+        source: buffer,
+    }
+}
+
+impl<'a> Lexer<'a> {
+    /// Source text covered by `span`
+    pub fn text(&self, span: Span) -> &'a str {
+        &self.source[span.start..span.end]
+    }
+
+    /// Copy of the remaining token stream, for looking further ahead than `peek`
+    pub fn lookahead(&self) -> Peekable<LexerBaseIter<'a>> {
+        self.tokens.clone()
+    }
+
+    pub fn peek(&mut self) -> Option<&SpannedToken> {
+        self.tokens.peek()
+    }
+
+    pub fn peek_token(&mut self) -> Option<Token> {
+        self.tokens.peek().map(|spanned| spanned.token)
+    }
+
+    pub fn next_if_token(&mut self, token: Token) -> Option<SpannedToken> {
+        self.tokens.next_if(|spanned| spanned.token == token)
+    }
+
+    /// Empty span just past the end of the source
+    pub fn end_span(&self) -> Span {
+        Span {
+            start: self.source.len(),
+            end: self.source.len(),
+        }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = SpannedToken;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tokens.next()
+    }
+}
+
+#[derive(Clone)]
 pub struct LexerBaseIter<'a> {
-    buffer_iter: Peekable<Chars<'a>>,
+    buffer: &'a str,
+    buffer_iter: Peekable<CharIndices<'a>>,
 }
 
 impl<'a> LexerBaseIter<'a> {
     pub fn new(buffer: &'a str) -> LexerBaseIter<'a> {
         LexerBaseIter {
-            buffer_iter: buffer.chars().peekable(),
+            buffer,
+            buffer_iter: buffer.char_indices().peekable(),
+        }
+    }
+
+    /// Byte offset of the next unconsumed character
+    fn offset(&mut self) -> usize {
+        match self.buffer_iter.peek() {
+            Some(&(idx, _)) => idx,
+            None => self.buffer.len(),
         }
     }
 }
 
 impl Iterator for LexerBaseIter<'_> {
-    type Item = Token;
+    type Item = SpannedToken;
     fn next(&mut self) -> Option<Self::Item> {
-        match self.buffer_iter.next()? {
+        let (start, first) = self.buffer_iter.next()?;
+        let token = match first {
             // Remove whitespace
-            ws if ws.is_whitespace() => self.next(),
-            '+' => Some(Token::Add),
-            '-' => Some(Token::Sub),
-            '*' => Some(Token::Mult),
-            '/' => Some(Token::Div),
-            '^' => Some(Token::Pow),
-            '(' => Some(Token::LParen),
-            ')' => Some(Token::RParen),
+            ws if ws.is_whitespace() => return self.next(),
+            '+' => Token::Add,
+            '-' => Token::Sub,
+            '*' => Token::Mult,
+            '/' => Token::Div,
+            '^' => Token::Pow,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            '=' => Token::Assign,
+            ';' => Token::Semicolon,
             // Parse float
-            mut digit if is_digit_char(&digit) => {
-                let mut has_dot = false;
-                let mut digit_str: String = String::from("");
-                loop {
-                    if digit == '.' {
-                        if has_dot {
-                            return Some(Token::Error(LexError::DoubleDecimal));
-                        }
-                        has_dot = true;
-                    }
-                    digit_str.push(digit);
-                    // Get the next char, break if it isn't a digit char
-                    match self.buffer_iter.next_if(is_digit_char) {
-                        Some(c) => digit = c,
-                        None => break,
+            digit if is_digit_char(&digit) => {
+                while self
+                    .buffer_iter
+                    .next_if(|(_, c)| is_digit_char(c))
+                    .is_some()
+                {}
+                let digit_str = &self.buffer[start..self.offset()];
+                if digit_str.matches('.').count() > 1 {
+                    Token::Error(LexError::DoubleDecimal)
+                } else {
+                    match f64::from_str(digit_str) {
+                        Ok(value) => Token::Float(value),
+                        Err(_) => Token::Error(LexError::InvalidNumber),
                     }
                 }
-                // Parse the characters into a float
-                Some(Token::Float(f64::from_str(&digit_str).unwrap()))
             }
-            // Parse Function, i, or z
-            mut id_char if is_id_char(&id_char) => {
-                let mut id_str: String = String::from("");
-                loop {
-                    id_str.push(id_char);
-                    match self.buffer_iter.next_if(is_id_char) {
-                        Some(c) => id_char = c,
-                        None => break,
-                    }
-                }
-                if id_str.len() == 1 {
-                    if id_str.starts_with('i') {
-                        return Some(Token::ComplexI);
-                    } else if id_str.starts_with('z') {
-                        return Some(Token::VarZ);
-                    }
+            // Parse Function, i, z, or a parameter name. Digits may follow the
+            // first character, as in `atan2`.
+            id_char if is_id_char(&id_char) => {
+                while self
+                    .buffer_iter
+                    .next_if(|(_, c)| is_id_char(c) || c.is_ascii_digit())
+                    .is_some()
+                {}
+                match &self.buffer[start..self.offset()] {
+                    "i" => Token::ComplexI,
+                    "z" => Token::VarZ,
+                    id_str => match fun_from_str(id_str) {
+                        Some(fun) => Token::Fun(fun),
+                        None => Token::Ident,
+                    },
                 }
-                Some(Token::Fun(fun_from_str(&id_str)?))
             }
             // Error: Invalid character
-            _ => Some(Token::Error(LexError::InvalidCharacter)),
-        }
+            _ => Token::Error(LexError::InvalidCharacter),
+        };
+        Some(SpannedToken {
+            token,
+            span: Span {
+                start,
+                end: self.offset(),
+            },
+        })
     }
 }
 
@@ -140,6 +240,22 @@ fn fun_from_str(str: &str) -> Option<Function> {
         "csch" => Some(Function::Csch),
         "Re" => Some(Function::Re),
         "Im" => Some(Function::Im),
+        "pow" => Some(Function::Pow),
+        "log" => Some(Function::Log),
+        "atan2" => Some(Function::Atan2),
+        "ln" => Some(Function::Ln),
+        "asin" => Some(Function::Asin),
+        "acos" => Some(Function::Acos),
+        "atan" => Some(Function::Atan),
+        "asinh" => Some(Function::Asinh),
+        "acosh" => Some(Function::Acosh),
+        "atanh" => Some(Function::Atanh),
+        "abs" => Some(Function::Abs),
+        "arg" => Some(Function::Arg),
+        "conj" => Some(Function::Conj),
+        "gamma" => Some(Function::Gamma),
+        "digamma" => Some(Function::Digamma),
+        "zeta" => Some(Function::Zeta),
         _ => None,
     }
 }
@@ -164,6 +280,9 @@ impl fmt::Display for LexError {
             match self {
                 LexError::InvalidCharacter => "Invalid Character",
                 LexError::DoubleDecimal => "Double Decimal",
+                LexError::InvalidNumber => "Invalid Number",
+                LexError::UnknownIdentifier =>
+                    "Unknown function. To multiply a parameter, write \"c (...)\" or \"c*(...)\"",
             }
         )
     }
@@ -191,6 +310,22 @@ impl fmt::Display for Function {
                 Function::Csch => "csch",
                 Function::Re => "Re",
                 Function::Im => "Im",
+                Function::Pow => "pow",
+                Function::Log => "log",
+                Function::Atan2 => "atan2",
+                Function::Ln => "ln",
+                Function::Asin => "asin",
+                Function::Acos => "acos",
+                Function::Atan => "atan",
+                Function::Asinh => "asinh",
+                Function::Acosh => "acosh",
+                Function::Atanh => "atanh",
+                Function::Abs => "abs",
+                Function::Arg => "arg",
+                Function::Conj => "conj",
+                Function::Gamma => "gamma",
+                Function::Digamma => "digamma",
+                Function::Zeta => "zeta",
             }
         )
     }
@@ -206,6 +341,7 @@ impl fmt::Display for Token {
                 Token::Float(r) => r.to_string(),
                 Token::ComplexI => "i".to_string(),
                 Token::VarZ => "z".to_string(),
+                Token::Ident => "identifier".to_string(),
                 Token::Fun(fun) => fun.to_string(),
                 Token::Sub => "-".to_string(),
                 Token::Mult => "*".to_string(),
@@ -213,6 +349,9 @@ impl fmt::Display for Token {
                 Token::Pow => "^".to_string(),
                 Token::LParen => "(".to_string(),
                 Token::RParen => ")".to_string(),
+                Token::Comma => ",".to_string(),
+                Token::Assign => "=".to_string(),
+                Token::Semicolon => ";".to_string(),
                 Token::Error(err) => err.to_string(),
             }
         )
@@ -1,6 +1,19 @@
 mod api;
 pub mod ast;
+pub mod bmp;
+pub mod bytecode;
+pub mod color;
+pub mod context;
+pub mod derivative;
 mod bridge_generated;
 pub mod domain_color;
+pub mod image;
+pub mod job;
 pub mod lexer;
 pub mod parser;
+pub mod printer;
+pub mod renderer;
+pub mod simplify;
+pub mod special;
+pub mod tiles;
+pub mod viewport;
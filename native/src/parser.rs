@@ -1,27 +1,262 @@
-use anyhow::{self, Error};
 use num::Complex;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
 
 use crate::ast::Node;
-use crate::lexer::{self, Lexer, Token};
+use crate::bytecode::Program;
+use crate::lexer::{self, LexError, Lexer, Span, SpannedToken, Token};
 
-type ComplexFnBox = Box<dyn Fn(Complex<f64>) -> Complex<f64>>;
+pub type ComplexFnBox = Box<dyn Fn(Complex<f64>) -> Complex<f64> + Send + Sync>;
 
-pub fn parse_to_fn(fun_str: &str) -> Result<ComplexFnBox, Error> {
+/// What the parser was looking for when it failed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Expected {
+    /// A constant, "z", "i", a parameter, an expression in parentheses, or a function call
+    Operand,
+    OpeningParen,
+    ClosingParen,
+    /// An operator or the end of the input
+    EndOfInput,
+    /// A comma before the next argument, or the end of the argument list
+    ArgumentSeparator,
+    /// The name of the function being defined
+    FunctionName,
+    /// A parameter name in the head of a function definition
+    ParameterName,
+    Assign,
+    Semicolon,
+}
+
+/// Error produced while parsing, located by the byte span of the offending input
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParseError {
+    /// The source at `span` could not be turned into a token
+    Lex { err: LexError, span: Span },
+    /// `found` appeared where `expected` was required
+    UnexpectedToken {
+        found: Token,
+        span: Span,
+        expected: Expected,
+    },
+    /// The input ended where `expected` was required. `span` is empty and
+    /// points just past the end of the input.
+    UnexpectedEnd { span: Span, expected: Expected },
+    /// The function at `span` was called with `found` arguments, but accepts
+    /// between `min` and `max`
+    WrongArgumentCount {
+        span: Span,
+        min: usize,
+        max: usize,
+        found: usize,
+    },
+    /// The function or parameter name at `span` is already defined
+    DuplicateDefinition { span: Span },
+    /// The function defined at `span` refers to itself
+    RecursiveDefinition { span: Span },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Lex { err: _, span } => *span,
+            ParseError::UnexpectedToken { span, .. } => *span,
+            ParseError::UnexpectedEnd { span, expected: _ } => *span,
+            ParseError::WrongArgumentCount { span, .. } => *span,
+            ParseError::DuplicateDefinition { span } => *span,
+            ParseError::RecursiveDefinition { span } => *span,
+        }
+    }
+}
+
+pub fn parse_to_fn(fun_str: &str) -> Result<ComplexFnBox, ParseError> {
     Ok(parse(fun_str)?.to_closure())
 }
 
-pub fn parse(str_buf: &str) -> Result<Node, Error> {
+pub fn parse_to_program(fun_str: &str) -> Result<Program, ParseError> {
+    Ok(Program::compile(&parse(fun_str)?))
+}
+
+/// A user-defined function, which is inlined at each call
+struct Definition {
+    arity: usize,
+    /// The parameters appear in the body under the names given by `bound_param`
+    body: Node,
+}
+
+impl Definition {
+    /// The body with the arguments of a call in place of the parameters
+    fn substitute(&self, args: &[Node]) -> Node {
+        let params: Vec<String> = (0..self.arity).map(bound_param).collect();
+        self.body.substitute(&params, args)
+    }
+}
+
+type Definitions = HashMap<String, Definition>;
+
+/// Name standing for the parameter at `idx` in the body of a definition. It
+/// isn't a valid identifier, so a free parameter inlined from another
+/// definition can't be captured by it.
+fn bound_param(idx: usize) -> String {
+    format!("#{idx}")
+}
+
+/// Names visible while parsing: the functions defined so far, and the
+/// parameters of the definition whose body is being parsed
+struct Scope<'a> {
+    defs: &'a Definitions,
+    params: &'a [String],
+}
+
+pub fn parse(str_buf: &str) -> Result<Node, ParseError> {
     let mut lexer = lexer::new_lexer(str_buf);
-    expr(&mut lexer)
+    let mut defs = Definitions::new();
+    while is_definition(&lexer) {
+        definition(&mut lexer, &mut defs)?;
+        if lexer.next_if_token(Token::Semicolon).is_none() {
+            return Err(unexpected(&mut lexer, Expected::Semicolon));
+        }
+    }
+    let scope = Scope {
+        defs: &defs,
+        params: &[],
+    };
+    let node = expr(&mut lexer, &scope)?;
+    match lexer.peek() {
+        None => Ok(node),
+        Some(_) => Err(unexpected(&mut lexer, Expected::EndOfInput)),
+    }
+}
+
+/// Builds the error for the next token (or the end of input) not matching `expected`
+fn unexpected(lexer: &mut Lexer, expected: Expected) -> ParseError {
+    match lexer.peek() {
+        Some(&SpannedToken {
+            token: Token::Error(err),
+            span,
+        }) => ParseError::Lex { err, span },
+        Some(&SpannedToken { token, span }) => ParseError::UnexpectedToken {
+            found: token,
+            span,
+            expected,
+        },
+        None => ParseError::UnexpectedEnd {
+            span: lexer.end_span(),
+            expected,
+        },
+    }
+}
+
+/// Whether `token` can begin a <base>. Lex errors are included so that they
+/// are reported by `base` instead of being treated as trailing input.
+fn starts_base(token: Option<Token>) -> bool {
+    matches!(
+        token,
+        Some(Token::Float(_))
+            | Some(Token::ComplexI)
+            | Some(Token::VarZ)
+            | Some(Token::Ident)
+            | Some(Token::Fun(_))
+            | Some(Token::LParen)
+            | Some(Token::Error(_))
+    )
+}
+
+fn check_arity(span: Span, arity: RangeInclusive<usize>, found: usize) -> Result<(), ParseError> {
+    if arity.contains(&found) {
+        Ok(())
+    } else {
+        Err(ParseError::WrongArgumentCount {
+            span,
+            min: *arity.start(),
+            max: *arity.end(),
+            found,
+        })
+    }
+}
+
+/// Parses the next token as an identifier, returning its span
+fn ident(lexer: &mut Lexer, expected: Expected) -> Result<Span, ParseError> {
+    match lexer.peek_token() {
+        Some(Token::Ident) => Ok(lexer.next().unwrap().span),
+        _ => Err(unexpected(lexer, expected)),
+    }
+}
+
+/// Whether the next statement, up to a ";" or the end of the input, is a
+/// function definition
+fn is_definition(lexer: &Lexer) -> bool {
+    lexer
+        .lookahead()
+        .map(|spanned| spanned.token)
+        .take_while(|&token| token != Token::Semicolon)
+        .any(|token| token == Token::Assign)
+}
+
+/// <definition> ::= <IDENT> <LPAREN> <IDENT> {<COMMA> <IDENT>} <RPAREN> <ASSIGN> <expr>
+fn definition(lexer: &mut Lexer, defs: &mut Definitions) -> Result<(), ParseError> {
+    let name_span = ident(lexer, Expected::FunctionName)?;
+    let name = lexer.text(name_span).to_string();
+    if defs.contains_key(&name) {
+        return Err(ParseError::DuplicateDefinition { span: name_span });
+    }
+
+    if lexer.next_if_token(Token::LParen).is_none() {
+        return Err(unexpected(lexer, Expected::OpeningParen));
+    }
+    let mut params: Vec<String> = Vec::new();
+    loop {
+        let span = ident(lexer, Expected::ParameterName)?;
+        let param = lexer.text(span).to_string();
+        if params.contains(&param) {
+            return Err(ParseError::DuplicateDefinition { span });
+        }
+        params.push(param);
+        if lexer.next_if_token(Token::Comma).is_none() {
+            break;
+        }
+    }
+    if lexer.next_if_token(Token::RParen).is_none() {
+        return Err(unexpected(lexer, Expected::ArgumentSeparator));
+    }
+    if lexer.next_if_token(Token::Assign).is_none() {
+        return Err(unexpected(lexer, Expected::Assign));
+    }
+
+    // The function isn't defined yet while its body is parsed, so a
+    // recursive call is reported as a call to an unknown function
+    let scope = Scope {
+        defs,
+        params: &params,
+    };
+    let body = expr(lexer, &scope).map_err(|err| match err {
+        ParseError::Lex {
+            err: LexError::UnknownIdentifier,
+            span,
+        } if lexer.text(span) == name => ParseError::RecursiveDefinition { span: name_span },
+        err => err,
+    })?;
+    // and a use of its name without a call is read as a parameter
+    if body.uses_param(&name) {
+        return Err(ParseError::RecursiveDefinition { span: name_span });
+    }
+    defs.insert(
+        name,
+        Definition {
+            arity: params.len(),
+            body,
+        },
+    );
+    Ok(())
 }
 
 macro_rules! bin_node_from_op {
-    ($lexer:expr, $op:ident, $left:expr, $right_fun:ident) => {
+    ($lexer:expr, $scope:expr, $op:ident, $left:expr, $right_fun:ident) => {
         Ok(match $op {
             Some(some_op) => Node::Binary {
-                op: some_op,
+                op: some_op.token,
                 left: Some(Box::new($left)),
-                right: Some(Box::new($right_fun($lexer)?)),
+                right: Some(Box::new($right_fun($lexer, $scope)?)),
             },
             None => $left,
         })
@@ -31,34 +266,34 @@ macro_rules! bin_node_from_op {
 /// Currently this sets the new node to the left child, and then
 /// at the end checks if it's the last one, and if so sets the last right
 /// to the right of the previous
-fn expr(lexer: &mut Lexer) -> Result<Node, Error> {
-    let mut current: Node = term(lexer)?;
-    while matches!(lexer.peek(), Some(&Token::Add) | Some(&Token::Sub)) {
-        let op = lexer.next().unwrap();
+fn expr(lexer: &mut Lexer, scope: &Scope) -> Result<Node, ParseError> {
+    let mut current: Node = term(lexer, scope)?;
+    while matches!(lexer.peek_token(), Some(Token::Add) | Some(Token::Sub)) {
+        let op = lexer.next().unwrap().token;
         current = Node::Binary {
             op,
             left: Some(Box::new(current)),
-            right: Some(Box::new(term(lexer)?)),
+            right: Some(Box::new(term(lexer, scope)?)),
         }
     }
     Ok(current)
 }
 
-fn term(lexer: &mut Lexer) -> Result<Node, Error> {
-    let mut current: Node = factor(lexer)?;
+fn term(lexer: &mut Lexer, scope: &Scope) -> Result<Node, ParseError> {
+    let mut current: Node = factor(lexer, scope)?;
     loop {
-        if matches!(lexer.peek(), Some(&Token::Mult) | Some(&Token::Div)) {
-            let op = lexer.next().unwrap();
+        if matches!(lexer.peek_token(), Some(Token::Mult) | Some(Token::Div)) {
+            let op = lexer.next().unwrap().token;
             current = Node::Binary {
                 op,
                 left: Some(Box::new(current)),
-                right: Some(Box::new(factor(lexer)?)),
+                right: Some(Box::new(factor(lexer, scope)?)),
             }
-        } else if let Ok(next) = factor(lexer) {
+        } else if starts_base(lexer.peek_token()) {
             current = Node::Binary {
                 op: Token::Mult,
                 left: Some(Box::new(current)),
-                right: Some(Box::new(next)),
+                right: Some(Box::new(factor(lexer, scope)?)),
             }
         } else {
             break;
@@ -67,69 +302,168 @@ fn term(lexer: &mut Lexer) -> Result<Node, Error> {
     Ok(current)
 }
 
-fn factor(lexer: &mut Lexer) -> Result<Node, Error> {
-    let left = base(lexer)?;
-    let op = lexer.next_if(|&op| op == Token::Pow);
-    bin_node_from_op!(lexer, op, left, factor)
+fn factor(lexer: &mut Lexer, scope: &Scope) -> Result<Node, ParseError> {
+    // <SUB> <factor>
+    if lexer.next_if_token(Token::Sub).is_some() {
+        return Ok(Node::Unary {
+            op: Token::Sub,
+            child: Some(Box::new(factor(lexer, scope)?)),
+        });
+    }
+    let left = base(lexer, scope)?;
+    let op = lexer.next_if_token(Token::Pow);
+    bin_node_from_op!(lexer, scope, op, left, factor)
 }
 
-fn base(lexer: &mut Lexer) -> Result<Node, Error> {
-    let first_tok = match lexer.peek() {
-        Some(tok) => *tok,
-        None => {
-            return Err(anyhow::anyhow!(
-            "Expected a constant, \"z\", \"i\", an expression in parentheses, or a function call. "
-        ))
-        }
-    };
-    match first_tok {
+fn base(lexer: &mut Lexer, scope: &Scope) -> Result<Node, ParseError> {
+    match lexer.peek_token() {
         // <FLOAT>
-        Token::Float(value) => {
+        Some(Token::Float(value)) => {
             lexer.next();
             Ok(Node::Const {
                 val: Complex::new(value, 0.0),
             })
         }
         // <COMPLEXI>
-        Token::ComplexI => {
+        Some(Token::ComplexI) => {
             lexer.next();
             Ok(Node::Const {
                 val: Complex::new(0.0, 1.0),
             })
         }
         // <VARZ>
-        Token::VarZ => {
+        Some(Token::VarZ) => {
             lexer.next();
             Ok(Node::Var)
         }
-        // <FUNCTION> <par_expr>
-        Token::Fun(fun) => {
-            lexer.next();
-            Ok(Node::Fun {
-                fun,
-                arg: Some(Box::new(par_expr(lexer)?)),
-            })
+        // <IDENT> <arg_list>, a call to a user-defined function
+        // <IDENT>, a parameter
+        Some(Token::Ident) => {
+            let span = lexer.next().unwrap().span;
+            let name = lexer.text(span);
+            // Parameters of the definition being parsed shadow functions
+            if let Some(idx) = scope.params.iter().position(|param| param == name) {
+                return Ok(Node::Param {
+                    name: bound_param(idx),
+                });
+            }
+            match scope.defs.get(name) {
+                Some(def) => {
+                    let args = arg_list(lexer, scope)?;
+                    check_arity(span, def.arity..=def.arity, args.len())?;
+                    Ok(def.substitute(&args))
+                }
+                // A parenthesis right after the name, without a space, is read
+                // as a call to an unknown (likely misspelled) function, so
+                // `c(z + 1)` is an error while `c (z + 1)` and `c*(z + 1)`
+                // multiply the parameter
+                None if matches!(
+                    lexer.peek(),
+                    Some(&SpannedToken { token: Token::LParen, span: paren }) if paren.start == span.end
+                ) =>
+                {
+                    Err(ParseError::Lex {
+                        err: LexError::UnknownIdentifier,
+                        span,
+                    })
+                }
+                None => Ok(Node::Param {
+                    name: name.to_string(),
+                }),
+            }
+        }
+        // <FUNCTION> <arg_list>
+        Some(Token::Fun(fun)) => {
+            let span = lexer.next().unwrap().span;
+            let args = arg_list(lexer, scope)?;
+            check_arity(span, fun.arity(), args.len())?;
+            Ok(Node::Fun { fun, args })
         }
         // <par_expr>
-        Token::LParen => par_expr(lexer),
+        Some(Token::LParen) => par_expr(lexer, scope),
         // Error: does not match a production for base
-        _ => Err(anyhow::anyhow!(
-            "Expected a constant, \"z\", \"i\", an expression in parentheses, or a function call. "
-        )),
+        _ => Err(unexpected(lexer, Expected::Operand)),
+    }
+}
+
+fn par_expr(lexer: &mut Lexer, scope: &Scope) -> Result<Node, ParseError> {
+    if lexer.next_if_token(Token::LParen).is_none() {
+        return Err(unexpected(lexer, Expected::OpeningParen));
+    }
+    let expr = expr(lexer, scope)?;
+    match lexer.next_if_token(Token::RParen) {
+        Some(_) => Ok(expr),
+        None => Err(unexpected(lexer, Expected::ClosingParen)),
     }
 }
 
-fn par_expr(lexer: &mut Lexer) -> Result<Node, Error> {
-    let l_paren = lexer.next_if(|&tok| tok == Token::LParen);
-    match l_paren {
-        Some(_) => {
-            let expr = expr(lexer);
-            let r_paren = lexer.next_if(|&tok| tok == Token::RParen);
-            match r_paren {
-                Some(_) => expr,
-                None => Err(anyhow::anyhow!("Expected a closing parenthesis")),
+fn arg_list(lexer: &mut Lexer, scope: &Scope) -> Result<Vec<Node>, ParseError> {
+    if lexer.next_if_token(Token::LParen).is_none() {
+        return Err(unexpected(lexer, Expected::OpeningParen));
+    }
+    let mut args = vec![expr(lexer, scope)?];
+    while lexer.next_if_token(Token::Comma).is_some() {
+        args.push(expr(lexer, scope)?);
+    }
+    match lexer.next_if_token(Token::RParen) {
+        Some(_) => Ok(args),
+        None => Err(unexpected(lexer, Expected::ArgumentSeparator)),
+    }
+}
+
+// `Display` implementations
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Expected::Operand =>
+                    "a constant, \"z\", \"i\", a parameter, an expression in parentheses, or a function call",
+                Expected::OpeningParen => "an opening parenthesis",
+                Expected::ClosingParen => "a closing parenthesis",
+                Expected::EndOfInput => "an operator or the end of the input",
+                Expected::ArgumentSeparator => "a comma or a closing parenthesis",
+                Expected::FunctionName => "the name of the function being defined",
+                Expected::ParameterName => "a parameter name",
+                Expected::Assign => "\"=\"",
+                Expected::Semicolon => "\";\"",
+            }
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Lex { err, span: _ } => write!(f, "{err}"),
+            ParseError::UnexpectedToken {
+                found,
+                span: _,
+                expected,
+            } => write!(f, "Unexpected \"{found}\", expected {expected}"),
+            ParseError::UnexpectedEnd { span: _, expected } => {
+                write!(f, "Unexpected end of input, expected {expected}")
+            }
+            ParseError::WrongArgumentCount {
+                span: _,
+                min,
+                max,
+                found,
+            } => {
+                if min == max {
+                    write!(f, "Expected {min} argument(s), found {found}")
+                } else {
+                    write!(f, "Expected {min} to {max} arguments, found {found}")
+                }
+            }
+            ParseError::DuplicateDefinition { span: _ } => write!(f, "Already defined"),
+            ParseError::RecursiveDefinition { span: _ } => {
+                write!(f, "Functions cannot refer to themselves")
             }
         }
-        None => Err(anyhow::anyhow!("Expected an opening parenthesis")),
     }
 }
+
+impl std::error::Error for ParseError {}
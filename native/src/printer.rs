@@ -0,0 +1,231 @@
+use crate::ast::Node;
+use crate::lexer::{Function, Token};
+use num::Complex;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Style {
+    Infix,
+    Latex,
+}
+
+// Binding strength of each kind of node, following the grammar: a child is
+// parenthesized when it binds more loosely than its position allows
+const PREC_SUM: u8 = 1;
+const PREC_PRODUCT: u8 = 2;
+const PREC_NEG: u8 = 3;
+const PREC_POW: u8 = 4;
+const PREC_ATOM: u8 = 5;
+
+impl Node {
+    /// Prints the tree as an expression that parses back to the same tree,
+    /// using as few parentheses as the grammar allows.
+    ///
+    /// Constants other than non-negative reals and `i` cannot be written as a
+    /// single literal, so they are printed as a parenthesized sum, e.g.
+    /// `(3 - 2i)`. Non-finite constants, which only arise from constant
+    /// folding, are printed as `inf` and `NaN` and do not parse back.
+    pub fn to_infix(&self) -> String {
+        print(self, Style::Infix)
+    }
+
+    /// Prints the tree as a LaTeX math-mode expression
+    pub fn to_latex(&self) -> String {
+        print(self, Style::Latex)
+    }
+}
+
+fn precedence(node: &Node, style: Style) -> u8 {
+    match node {
+        Node::Binary { op, .. } => match op {
+            Token::Add | Token::Sub => PREC_SUM,
+            // \frac groups its operands, so it never needs parentheses
+            Token::Div if style == Style::Latex => PREC_ATOM,
+            Token::Mult | Token::Div => PREC_PRODUCT,
+            _ => PREC_POW,
+        },
+        Node::Unary { .. } => PREC_NEG,
+        // Negative numbers are written with a leading minus
+        Node::Const { val } if val.im == 0.0 && !is_literal(*val) => PREC_NEG,
+        _ => PREC_ATOM,
+    }
+}
+
+/// Whether `val` is written as a plain number
+fn is_literal(val: Complex<f64>) -> bool {
+    val.im == 0.0 && (val.re.is_sign_positive() || val.re.is_nan())
+}
+
+fn is_imaginary_unit(val: Complex<f64>) -> bool {
+    val == Complex::new(0.0, 1.0)
+}
+
+/// Whether the printed form of `node` starts with a letter, so that it can be
+/// juxtaposed with a preceding number as in `2z`
+fn starts_with_letter(node: &Node) -> bool {
+    match node {
+        Node::Var | Node::Param { .. } | Node::Fun { .. } => true,
+        Node::Const { val } => is_imaginary_unit(*val),
+        Node::Binary { op, left, .. } if *op != Token::Add && *op != Token::Sub => {
+            left.as_deref().is_some_and(starts_with_letter)
+        }
+        _ => false,
+    }
+}
+
+fn print(node: &Node, style: Style) -> String {
+    match node {
+        Node::Const { val } => print_const(*val, style),
+        Node::Var => "z".to_string(),
+        Node::Param { name } => match style {
+            Style::Latex if name.chars().count() > 1 => format!("\\mathit{{{name}}}"),
+            _ => name.clone(),
+        },
+        Node::Binary { op, left, right } => {
+            let (left, right) = (left.as_deref().unwrap(), right.as_deref().unwrap());
+            let prec = precedence(node, style);
+            match (op, style) {
+                (Token::Div, Style::Latex) => {
+                    format!(
+                        "\\frac{{{}}}{{{}}}",
+                        print(left, style),
+                        print(right, style)
+                    )
+                }
+                (Token::Pow, _) => {
+                    // The base must be a <base>, the exponent may be any <factor>
+                    let base = child(left, PREC_ATOM, style);
+                    match style {
+                        Style::Infix => format!("{base}^{}", child(right, PREC_NEG, style)),
+                        Style::Latex => format!("{{{base}}}^{{{}}}", print(right, style)),
+                    }
+                }
+                (Token::Mult, _) if is_number(left) && starts_with_letter(right) => {
+                    let right = child(right, prec + 1, style);
+                    match style {
+                        Style::Infix => format!("{}{right}", print(left, style)),
+                        Style::Latex => format!("{} {right}", print(left, style)),
+                    }
+                }
+                _ => {
+                    // Operators are left associative, so only the right operand
+                    // needs parentheses at equal precedence
+                    let symbol = match (op, style) {
+                        (Token::Mult, Style::Latex) => "\\cdot".to_string(),
+                        _ => op.to_string(),
+                    };
+                    format!(
+                        "{} {symbol} {}",
+                        child(left, prec, style),
+                        child(right, prec + 1, style)
+                    )
+                }
+            }
+        }
+        Node::Unary { op, child: operand } => {
+            format!(
+                "{op}{}",
+                child(operand.as_deref().unwrap(), PREC_NEG, style)
+            )
+        }
+        Node::Fun { fun, args } => print_fun(*fun, args, style),
+    }
+}
+
+fn is_number(node: &Node) -> bool {
+    matches!(node, Node::Const { val } if is_literal(*val) && val.re.is_finite())
+}
+
+/// Prints `node`, in parentheses if it binds more loosely than `min_prec`
+fn child(node: &Node, min_prec: u8, style: Style) -> String {
+    let printed = print(node, style);
+    if precedence(node, style) >= min_prec {
+        printed
+    } else {
+        match style {
+            Style::Infix => format!("({printed})"),
+            Style::Latex => format!("\\left({printed}\\right)"),
+        }
+    }
+}
+
+fn print_real(re: f64, style: Style) -> String {
+    match (re, style) {
+        (re, Style::Latex) if re.is_nan() => "\\mathrm{NaN}".to_string(),
+        (re, Style::Latex) if re.is_infinite() => {
+            if re > 0.0 { "\\infty" } else { "-\\infty" }.to_string()
+        }
+        (re, _) => re.to_string(),
+    }
+}
+
+fn print_const(val: Complex<f64>, style: Style) -> String {
+    if is_literal(val) {
+        return print_real(val.re, style);
+    }
+    if is_imaginary_unit(val) {
+        return "i".to_string();
+    }
+    if val.im == 0.0 {
+        return format!("-{}", print_real(-val.re, style));
+    }
+    let im = match val.im.abs() {
+        1.0 => "i".to_string(),
+        im => format!("{}i", print_real(im, style)),
+    };
+    let sum = match (val.re == 0.0, val.im.is_sign_negative()) {
+        (true, false) => im,
+        (true, true) => format!("-{im}"),
+        (false, false) => format!("{} + {im}", print_real(val.re, style)),
+        (false, true) => format!("{} - {im}", print_real(val.re, style)),
+    };
+    match style {
+        Style::Infix => format!("({sum})"),
+        Style::Latex => format!("\\left({sum}\\right)"),
+    }
+}
+
+fn print_fun(fun: Function, args: &[Node], style: Style) -> String {
+    let printed: Vec<String> = args.iter().map(|arg| print(arg, style)).collect();
+    if style == Style::Infix {
+        return format!("{fun}({})", printed.join(", "));
+    }
+    match (fun, printed.as_slice()) {
+        (Function::Sqrt, [arg]) => format!("\\sqrt{{{arg}}}"),
+        (Function::Abs, [arg]) => format!("\\left|{arg}\\right|"),
+        (Function::Conj, [arg]) => format!("\\overline{{{arg}}}"),
+        (Function::Exp, [arg]) => format!("e^{{{arg}}}"),
+        (Function::Pow, [_, exp]) => {
+            format!("{{{}}}^{{{exp}}}", child(&args[0], PREC_ATOM, style))
+        }
+        (Function::Log, [arg, base]) => format!("\\log_{{{base}}}\\left({arg}\\right)"),
+        (fun, printed) => format!("{}\\left({}\\right)", latex_name(fun), printed.join(", ")),
+    }
+}
+
+fn latex_name(fun: Function) -> String {
+    match fun {
+        Function::Sin
+        | Function::Cos
+        | Function::Tan
+        | Function::Cot
+        | Function::Sec
+        | Function::Csc
+        | Function::Sinh
+        | Function::Cosh
+        | Function::Tanh
+        | Function::Coth
+        | Function::Log
+        | Function::Ln
+        | Function::Arg => format!("\\{fun}"),
+        Function::Asin => "\\arcsin".to_string(),
+        Function::Acos => "\\arccos".to_string(),
+        Function::Atan => "\\arctan".to_string(),
+        Function::Asinh => "\\operatorname{arsinh}".to_string(),
+        Function::Acosh => "\\operatorname{arcosh}".to_string(),
+        Function::Atanh => "\\operatorname{artanh}".to_string(),
+        Function::Gamma => "\\Gamma".to_string(),
+        Function::Digamma => "\\psi".to_string(),
+        Function::Zeta => "\\zeta".to_string(),
+        fun => format!("\\operatorname{{{fun}}}"),
+    }
+}
@@ -0,0 +1,148 @@
+use crate::domain_color::{self, DCOptions, Plotter, RenderError};
+use crate::image::Image;
+use crate::job::RenderJob;
+use crate::viewport::Viewport;
+use num::Complex;
+
+/// Keeps the last rendered image of a function so that panning only has to
+/// color the newly exposed pixels
+pub struct Renderer {
+    plotter: Plotter,
+    viewport: Viewport,
+    image: Image,
+}
+
+impl Renderer {
+    /// Renders the whole image for the effective viewport of `options`
+    pub fn new(
+        width: usize,
+        height: usize,
+        fun_str: &str,
+        options: &DCOptions,
+    ) -> Result<Self, RenderError> {
+        domain_color::validate(width, height, options)?;
+        let mut renderer = Renderer {
+            plotter: Plotter::new(fun_str, options)?,
+            viewport: options.effective_viewport(width, height),
+            image: Image::new(width, height),
+        };
+        renderer.render_all();
+        Ok(renderer)
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// The viewport shown in the current image
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    /// Shows a new viewport, re-rendering the whole image.
+    ///
+    /// The viewport is used as is, even if its aspect ratio differs from the
+    /// image's.
+    pub fn set_viewport(&mut self, viewport: Viewport) -> Result<&Image, RenderError> {
+        let options = DCOptions {
+            viewport,
+            ..DCOptions::default()
+        };
+        domain_color::validate(self.image.width, self.image.height, &options)?;
+        self.viewport = viewport;
+        self.render_all();
+        Ok(&self.image)
+    }
+
+    /// Zooms in by `factor` (out if it is below 1), keeping the point under
+    /// the pixel coordinates `(x, y)` in place
+    pub fn zoom(&mut self, factor: f64, x: f64, y: f64) -> Result<&Image, RenderError> {
+        let (width, height) = (self.image.width, self.image.height);
+        let fixed = self.viewport.pixel_to_complex(x, y, width, height);
+        let shrink = |min: f64, max: f64, fixed: f64| {
+            (
+                fixed + (min - fixed) / factor,
+                fixed + (max - fixed) / factor,
+            )
+        };
+        let (xmin, xmax) = shrink(self.viewport.xmin, self.viewport.xmax, fixed.re);
+        let (ymin, ymax) = shrink(self.viewport.ymin, self.viewport.ymax, fixed.im);
+        self.set_viewport(Viewport::new(xmin, xmax, ymin, ymax))
+    }
+
+    /// Moves the image content by `dx` pixels to the right and `dy` pixels
+    /// down, as when dragging the plot, so the viewport moves the opposite way.
+    ///
+    /// The pixels still in view are shifted, and only the strips exposed along
+    /// the edges are evaluated.
+    pub fn pan(&mut self, dx: i64, dy: i64) -> &Image {
+        let (width, height) = (self.image.width, self.image.height);
+        let step = Complex::new(
+            self.viewport.width() / width as f64,
+            self.viewport.height() / height as f64,
+        );
+        self.viewport = Viewport::new(
+            self.viewport.xmin - dx as f64 * step.re,
+            self.viewport.xmax - dx as f64 * step.re,
+            self.viewport.ymin + dy as f64 * step.im,
+            self.viewport.ymax + dy as f64 * step.im,
+        );
+        if dx.unsigned_abs() >= width as u64 || dy.unsigned_abs() >= height as u64 {
+            self.render_all();
+            return &self.image;
+        }
+        let (dx_abs, dy_abs) = (dx.unsigned_abs() as usize, dy.unsigned_abs() as usize);
+        let stride = 4 * width;
+
+        // Shift whole rows, then the pixels within each row
+        let pixels = &mut self.image.pixels;
+        if dy > 0 {
+            pixels.copy_within(..stride * (height - dy_abs), stride * dy_abs);
+        } else if dy < 0 {
+            pixels.copy_within(stride * dy_abs.., 0);
+        }
+        if dx != 0 {
+            for row in pixels.chunks_exact_mut(stride) {
+                if dx > 0 {
+                    row.copy_within(..4 * (width - dx_abs), 4 * dx_abs);
+                } else {
+                    row.copy_within(4 * dx_abs.., 0);
+                }
+            }
+        }
+
+        // Exposed rows across the full width, then exposed columns in the remaining rows
+        let (exposed_rows, kept_rows) = if dy >= 0 {
+            (0..dy_abs, dy_abs..height)
+        } else {
+            (height - dy_abs..height, 0..height - dy_abs)
+        };
+        let exposed_cols = if dx >= 0 {
+            0..dx_abs
+        } else {
+            width - dx_abs..width
+        };
+        let job = RenderJob::default();
+        for (cols, rows) in [(0..width, exposed_rows), (exposed_cols, kept_rows)] {
+            self.plotter
+                .color_region(&self.viewport, width, height, pixels, cols, rows, &job)
+                .expect("Renders without a cancelled job cannot fail");
+        }
+        &self.image
+    }
+
+    fn render_all(&mut self) {
+        let (width, height) = (self.image.width, self.image.height);
+        self.plotter
+            .color_region(
+                &self.viewport,
+                width,
+                height,
+                &mut self.image.pixels,
+                0..width,
+                0..height,
+                &RenderJob::default(),
+            )
+            .expect("Renders without a cancelled job cannot fail");
+    }
+}
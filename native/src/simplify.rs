@@ -0,0 +1,131 @@
+use crate::ast::Node;
+use crate::lexer::Token;
+use crate::special;
+use num::Complex;
+
+fn is_real_const(node: &Node, re: f64) -> bool {
+    matches!(node, Node::Const { val } if *val == Complex::new(re, 0.0))
+}
+
+fn binary(op: Token, left: Node, right: Node) -> Node {
+    Node::Binary {
+        op,
+        left: Some(Box::new(left)),
+        right: Some(Box::new(right)),
+    }
+}
+
+/// Negation of `node`, folded into constants and cancelling double negation
+fn negate(node: Node) -> Node {
+    match node {
+        Node::Const { val } => Node::Const { val: -val },
+        Node::Unary {
+            op: Token::Sub,
+            child,
+        } => *child.unwrap(),
+        node => Node::Unary {
+            op: Token::Sub,
+            child: Some(Box::new(node)),
+        },
+    }
+}
+
+/// Splits a negation `-a` into `Ok(a)`, leaving any other node as `Err(node)`.
+/// Negative real constants count as negations when `with_consts` is set.
+fn split_negation(node: Node, with_consts: bool) -> Result<Node, Node> {
+    match node {
+        Node::Unary {
+            op: Token::Sub,
+            child,
+        } => Ok(*child.unwrap()),
+        Node::Const { val } if with_consts && val.re < 0.0 && val.im == 0.0 => {
+            Ok(Node::Const { val: -val })
+        }
+        node => Err(node),
+    }
+}
+
+fn fold(op: Token, a: Complex<f64>, b: Complex<f64>) -> Complex<f64> {
+    match op {
+        Token::Add => a + b,
+        Token::Sub => a - b,
+        Token::Mult => a * b,
+        Token::Div => special::div(a, b),
+        Token::Pow => a.powc(b),
+        _ => panic!("Error in simplification (invalid binary operator), please report this to program maintainer"),
+    }
+}
+
+fn simplify_binary(op: Token, left: Node, right: Node) -> Node {
+    // Constant folding
+    if let (Node::Const { val: a }, Node::Const { val: b }) = (&left, &right) {
+        return Node::Const {
+            val: fold(op, *a, *b),
+        };
+    }
+
+    match op {
+        Token::Add if is_real_const(&right, 0.0) => left,
+        Token::Add if is_real_const(&left, 0.0) => right,
+        Token::Sub if is_real_const(&right, 0.0) => left,
+        Token::Sub if is_real_const(&left, 0.0) => negate(right),
+        Token::Mult if is_real_const(&right, 1.0) => left,
+        Token::Mult if is_real_const(&left, 1.0) => right,
+        Token::Div | Token::Pow if is_real_const(&right, 1.0) => left,
+        // a + -b = a - b, and -a + b = b - a
+        Token::Add => match split_negation(right, true) {
+            Ok(right) => binary(Token::Sub, left, right),
+            Err(right) => match split_negation(left, false) {
+                Ok(left) => binary(Token::Sub, right, left),
+                Err(left) => binary(Token::Add, left, right),
+            },
+        },
+        // a - -b = a + b
+        Token::Sub => match split_negation(right, true) {
+            Ok(right) => binary(Token::Add, left, right),
+            Err(right) => binary(Token::Sub, left, right),
+        },
+        // Negations are moved out of products and quotients, where they cancel
+        Token::Mult | Token::Div => {
+            match (split_negation(left, false), split_negation(right, false)) {
+                (Ok(left), Ok(right)) | (Err(left), Err(right)) => binary(op, left, right),
+                (Ok(left), Err(right)) | (Err(left), Ok(right)) => negate(binary(op, left, right)),
+            }
+        }
+        _ => binary(op, left, right),
+    }
+}
+
+impl Node {
+    /// Simplifies the tree without changing the value it evaluates to.
+    ///
+    /// Constant subtrees are folded, the identities `x + 0`, `x - 0`, `x * 1`,
+    /// `x / 1` and `x ^ 1` are removed, and negations are folded into
+    /// constants, turned into subtraction, or moved out of products so that
+    /// double negations cancel. Common subexpressions are shared later, when
+    /// the tree is compiled to a [`crate::bytecode::Program`].
+    pub fn simplify(self) -> Node {
+        match self {
+            Node::Binary { op, left, right } => {
+                simplify_binary(op, left.unwrap().simplify(), right.unwrap().simplify())
+            }
+            Node::Unary {
+                op: Token::Sub,
+                child,
+            } => negate(child.unwrap().simplify()),
+            Node::Fun { fun, args } => {
+                let args: Vec<Node> = args.into_iter().map(Node::simplify).collect();
+                match args.as_slice() {
+                    [Node::Const { val }] => Node::Const {
+                        val: fun.apply(*val),
+                    },
+                    [Node::Const { val: a }, Node::Const { val: b }] => Node::Const {
+                        val: fun.apply2(*a, *b),
+                    },
+                    _ => Node::Fun { fun, args },
+                }
+            }
+            node => node,
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use crate::domain_color::{self, DCOptions, Plotter, RenderError};
+use crate::image::Image;
+use crate::job::RenderJob;
+use crate::viewport::Viewport;
+use rayon::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// Width and height of a tile in pixels
+pub const TILE_SIZE: usize = 256;
+
+/// Bytes of pixel data in a tile
+pub const TILE_BYTES: usize = 4 * TILE_SIZE * TILE_SIZE;
+
+/// Most tiles a single request may cover
+pub const MAX_TILES: usize = 1024;
+
+/// Range of zoom levels, from tiles of side `2^40` down to tiles whose pixels
+/// are about as small as the spacing of doubles near 1
+pub const MIN_ZOOM: i32 = -40;
+pub const MAX_ZOOM: i32 = 40;
+
+/// Identifies a tile of a particular plot.
+///
+/// At zoom level `zoom` tiles are squares of side `2^-zoom` in the plane, and
+/// the tile `(x, y)` covers `[x, x + 1] * 2^-zoom` horizontally and
+/// `[y, y + 1] * 2^-zoom` vertically, so `y` grows upwards.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    /// Hash of the expression and of every option that affects the colors
+    pub plot: u64,
+    pub zoom: i32,
+    pub x: i64,
+    pub y: i64,
+}
+
+/// Side of the tiles at a zoom level, in units of the plane
+pub fn tile_side(zoom: i32) -> f64 {
+    2f64.powi(zoom.saturating_neg())
+}
+
+/// The square of the plane covered by tile `(x, y)` at zoom level `zoom`
+pub fn tile_viewport(zoom: i32, x: i64, y: i64) -> Viewport {
+    let side = tile_side(zoom);
+    Viewport::new(
+        x as f64 * side,
+        (x + 1) as f64 * side,
+        y as f64 * side,
+        (y + 1) as f64 * side,
+    )
+}
+
+/// Lowest zoom level whose pixels are no larger than those of a `width` by
+/// `height` image of `viewport`, so the tiles are at least as sharp, clamped
+/// to [`MIN_ZOOM`] and [`MAX_ZOOM`]
+pub fn zoom_for(viewport: &Viewport, width: usize, height: usize) -> i32 {
+    let pixel = (viewport.width() / width as f64).min(viewport.height() / height as f64);
+    let zoom = -(pixel * TILE_SIZE as f64).log2();
+    if zoom.is_nan() {
+        return MAX_ZOOM;
+    }
+    (zoom.ceil() as i32).clamp(MIN_ZOOM, MAX_ZOOM)
+}
+
+/// First and last columns, then first and last rows, of the tiles at zoom
+/// level `zoom` overlapping `viewport`
+fn tile_bounds(viewport: &Viewport, zoom: i32) -> ((i64, i64), (i64, i64)) {
+    let side = tile_side(zoom);
+    let first = |min: f64| (min / side).floor() as i64;
+    let last = |max: f64| ((max / side).ceil() as i64).saturating_sub(1);
+    (
+        (first(viewport.xmin), last(viewport.xmax)),
+        (first(viewport.ymin), last(viewport.ymax)),
+    )
+}
+
+/// Number of tiles at zoom level `zoom` overlapping `viewport`, or `None` if
+/// it overflows
+pub fn tile_count(viewport: &Viewport, zoom: i32) -> Option<usize> {
+    let ((x0, x1), (y0, y1)) = tile_bounds(viewport, zoom);
+    let len = |first: i64, last: i64| -> Option<usize> {
+        if last < first {
+            return Some(0);
+        }
+        usize::try_from(last.checked_sub(first)?)
+            .ok()?
+            .checked_add(1)
+    };
+    len(x0, x1)?.checked_mul(len(y0, y1)?)
+}
+
+/// Tiles at zoom level `zoom` overlapping `viewport`, row by row from the top
+/// left. Check [`tile_count`] first, as the list can be huge.
+pub fn tiles_covering(viewport: &Viewport, zoom: i32) -> Vec<(i64, i64)> {
+    let ((x0, x1), (y0, y1)) = tile_bounds(viewport, zoom);
+    (y0..=y1)
+        .rev()
+        .flat_map(|y| (x0..=x1).map(move |x| (x, y)))
+        .collect()
+}
+
+/// Hash identifying the pixels of the tiles of `fun_str` with `options`. The
+/// viewport is ignored since tiles have their own.
+pub fn plot_hash(fun_str: &str, options: &DCOptions) -> u64 {
+    let options = DCOptions {
+        viewport: Viewport::default(),
+        preserve_aspect: false,
+        ..options.clone()
+    };
+    // The debug representation covers every option, with floats printed exactly
+    let mut hasher = DefaultHasher::new();
+    format!("{fun_str}\n{options:?}").hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A rendered tile
+#[derive(Clone, Debug)]
+pub struct Tile {
+    pub key: TileKey,
+    pub viewport: Viewport,
+    pub image: Arc<Image>,
+}
+
+/// Least recently used cache of tiles, holding at most `budget` bytes of pixels
+pub struct TileCache {
+    budget: usize,
+    tiles: HashMap<TileKey, (Arc<Image>, u64)>,
+    /// Incremented on every access, to order the tiles by last use
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl TileCache {
+    pub fn new(budget: usize) -> Self {
+        TileCache {
+            budget,
+            tiles: HashMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &TileKey) -> Option<Arc<Image>> {
+        self.clock += 1;
+        match self.tiles.get_mut(key) {
+            Some((image, last_used)) => {
+                *last_used = self.clock;
+                self.hits += 1;
+                Some(image.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Adds a tile, evicting the least recently used ones to stay within the budget
+    pub fn insert(&mut self, key: TileKey, image: Arc<Image>) {
+        self.clock += 1;
+        self.tiles.insert(key, (image, self.clock));
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.memory_used() > self.budget {
+            let oldest = *self
+                .tiles
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .unwrap()
+                .0;
+            self.tiles.remove(&oldest);
+        }
+    }
+
+    pub fn contains(&self, key: &TileKey) -> bool {
+        self.tiles.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Bytes of pixels held by the cached tiles
+    pub fn memory_used(&self) -> usize {
+        self.tiles
+            .values()
+            .map(|(image, _)| image.pixels.len())
+            .sum()
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict();
+    }
+
+    /// Lookups that found their tile, and those that did not
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+    }
+}
+
+/// Renders tiles on demand, reusing those already in its cache
+pub struct TileRenderer {
+    cache: TileCache,
+}
+
+impl TileRenderer {
+    /// Renderer whose cache holds at most `budget` bytes of pixels
+    pub fn new(budget: usize) -> Self {
+        TileRenderer {
+            cache: TileCache::new(budget),
+        }
+    }
+
+    pub fn cache(&self) -> &TileCache {
+        &self.cache
+    }
+
+    pub fn cache_mut(&mut self) -> &mut TileCache {
+        &mut self.cache
+    }
+
+    /// The tiles covering `viewport` at zoom level `zoom`, row by row from the
+    /// top left. The viewport of `options` is ignored.
+    ///
+    /// Missing tiles are rendered in parallel and added to the cache. If they
+    /// do not all fit in its budget, the earliest ones are evicted but still
+    /// returned.
+    pub fn tiles(
+        &mut self,
+        fun_str: &str,
+        options: &DCOptions,
+        viewport: &Viewport,
+        zoom: i32,
+    ) -> Result<Vec<Tile>, RenderError> {
+        let options = DCOptions {
+            viewport: *viewport,
+            preserve_aspect: false,
+            ..options.clone()
+        };
+        domain_color::validate(TILE_SIZE, TILE_SIZE, &options)?;
+        match tile_count(viewport, zoom) {
+            Some(count) if count <= MAX_TILES => (),
+            count => {
+                return Err(RenderError::TooManyTiles {
+                    count: count.unwrap_or(usize::MAX),
+                })
+            }
+        }
+        let covering = tiles_covering(viewport, zoom);
+
+        let plot = plot_hash(fun_str, &options);
+        let keys: Vec<TileKey> = covering
+            .into_iter()
+            .map(|(x, y)| TileKey { plot, zoom, x, y })
+            .collect();
+        let cached: Vec<Option<Arc<Image>>> = keys.iter().map(|key| self.cache.get(key)).collect();
+
+        let missing: Vec<TileKey> = keys
+            .iter()
+            .zip(&cached)
+            .filter(|(_, image)| image.is_none())
+            .map(|(key, _)| *key)
+            .collect();
+        let mut rendered: HashMap<TileKey, Arc<Image>> = HashMap::new();
+        if !missing.is_empty() {
+            let plotter = Plotter::new(fun_str, &options)?;
+            let job = RenderJob::default();
+            let images = missing
+                .par_iter()
+                .map(|key| {
+                    let mut image = Image::new(TILE_SIZE, TILE_SIZE);
+                    plotter.color_region(
+                        &tile_viewport(key.zoom, key.x, key.y),
+                        TILE_SIZE,
+                        TILE_SIZE,
+                        &mut image.pixels,
+                        0..TILE_SIZE,
+                        0..TILE_SIZE,
+                        &job,
+                    )?;
+                    Ok(Arc::new(image))
+                })
+                .collect::<Result<Vec<_>, RenderError>>()?;
+            for (key, image) in missing.into_iter().zip(images) {
+                self.cache.insert(key, image.clone());
+                rendered.insert(key, image);
+            }
+        }
+
+        Ok(keys
+            .into_iter()
+            .zip(cached)
+            .map(|(key, image)| Tile {
+                key,
+                viewport: tile_viewport(key.zoom, key.x, key.y),
+                image: image.unwrap_or_else(|| rendered[&key].clone()),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,87 @@
+use num::Complex;
+
+/// The rectangle of the complex plane shown in an image
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub xmin: f64,
+    pub xmax: f64,
+    pub ymin: f64,
+    pub ymax: f64,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport {
+            xmin: -5.0,
+            xmax: 5.0,
+            ymin: -5.0,
+            ymax: 5.0,
+        }
+    }
+}
+
+impl Viewport {
+    pub fn new(xmin: f64, xmax: f64, ymin: f64, ymax: f64) -> Self {
+        Viewport {
+            xmin,
+            xmax,
+            ymin,
+            ymax,
+        }
+    }
+
+    /// Viewport of a `width` by `height` pixel image centered on `center`, where
+    /// each pixel spans `scale` units in both directions
+    pub fn centered(center: Complex<f64>, scale: f64, width: usize, height: usize) -> Self {
+        let (half_width, half_height) = (0.5 * scale * width as f64, 0.5 * scale * height as f64);
+        Viewport {
+            xmin: center.re - half_width,
+            xmax: center.re + half_width,
+            ymin: center.im - half_height,
+            ymax: center.im + half_height,
+        }
+    }
+
+    pub fn width(&self) -> f64 {
+        self.xmax - self.xmin
+    }
+
+    pub fn height(&self) -> f64 {
+        self.ymax - self.ymin
+    }
+
+    pub fn center(&self) -> Complex<f64> {
+        Complex::new(0.5 * (self.xmin + self.xmax), 0.5 * (self.ymin + self.ymax))
+    }
+
+    /// Width over height
+    pub fn aspect(&self) -> f64 {
+        self.width() / self.height()
+    }
+
+    /// Grows the shorter side about the center so that the viewport has the
+    /// aspect ratio of a `width` by `height` image, making pixels square
+    pub fn fit_aspect(&self, width: usize, height: usize) -> Viewport {
+        let scale = (self.width() / width as f64).max(self.height() / height as f64);
+        Viewport::centered(self.center(), scale, width, height)
+    }
+
+    /// Point of the plane at pixel coordinates `(x, y)` of a `width` by
+    /// `height` image, where `(0, 0)` is the top left corner of the image and
+    /// `(width, height)` the bottom right one. The center of the pixel in
+    /// column `i` and row `j` is at `(i + 0.5, j + 0.5)`.
+    pub fn pixel_to_complex(&self, x: f64, y: f64, width: usize, height: usize) -> Complex<f64> {
+        Complex::new(
+            self.xmin + x * self.width() / width as f64,
+            self.ymax - y * self.height() / height as f64,
+        )
+    }
+
+    /// Pixel coordinates of `z`, the inverse of [`Viewport::pixel_to_complex`]
+    pub fn complex_to_pixel(&self, z: Complex<f64>, width: usize, height: usize) -> (f64, f64) {
+        (
+            (z.re - self.xmin) / self.width() * width as f64,
+            (self.ymax - z.im) / self.height() * height as f64,
+        )
+    }
+}
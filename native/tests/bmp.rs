@@ -0,0 +1,122 @@
+use native::bmp::{self, BmpError, BmpOptions};
+use native::image::Image;
+
+const ALL_OPTIONS: [BmpOptions; 4] = [
+    BmpOptions {
+        top_down: false,
+        alpha: false,
+    },
+    BmpOptions {
+        top_down: true,
+        alpha: false,
+    },
+    BmpOptions {
+        top_down: false,
+        alpha: true,
+    },
+    BmpOptions {
+        top_down: true,
+        alpha: true,
+    },
+];
+
+/// Image with a distinct color in every pixel
+fn test_image(width: usize, height: usize, opaque: bool) -> Image {
+    let mut image = Image::new(width, height);
+    for (idx, pixel) in image.pixels.chunks_exact_mut(4).enumerate() {
+        pixel[0] = (idx * 7) as u8;
+        pixel[1] = (idx * 13 + 1) as u8;
+        pixel[2] = (idx * 31 + 2) as u8;
+        if !opaque {
+            pixel[3] = (idx * 5) as u8;
+        }
+    }
+    image
+}
+
+#[test]
+fn test_round_trip() {
+    for options in ALL_OPTIONS {
+        for (width, height) in [(1, 1), (2, 3), (3, 2), (5, 4), (7, 1), (16, 9)] {
+            let image = test_image(width, height, !options.alpha);
+            let encoded = bmp::encode(&image, options);
+            assert_eq!(
+                Some(encoded.len()),
+                bmp::file_size(width, height, options),
+                "{width}x{height} {options:?}"
+            );
+            assert_eq!(
+                bmp::decode(&encoded),
+                Ok(image),
+                "{width}x{height} {options:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_layout() {
+    let image = test_image(3, 2, true);
+
+    // 24-bit rows of 9 bytes are padded to 12, bottom row first
+    let bmp = bmp::encode(&image, BmpOptions::default());
+    assert_eq!(bmp.len(), 54 + 2 * 12);
+    assert_eq!(u32::from_le_bytes(bmp[2..6].try_into().unwrap()), 78);
+    assert_eq!(u32::from_le_bytes(bmp[10..14].try_into().unwrap()), 54);
+    assert_eq!(i32::from_le_bytes(bmp[22..26].try_into().unwrap()), 2);
+    let bottom_left = image.pixel(0, 1);
+    assert_eq!(bmp[54..57], [bottom_left.b, bottom_left.g, bottom_left.r]);
+    assert_eq!(bmp[63..66], [0, 0, 0]);
+    let top_left = image.pixel(0, 0);
+    assert_eq!(bmp[66..69], [top_left.b, top_left.g, top_left.r]);
+
+    // Top-down images have a negative height
+    let top_down = bmp::encode(
+        &image,
+        BmpOptions {
+            top_down: true,
+            alpha: false,
+        },
+    );
+    assert_eq!(i32::from_le_bytes(top_down[22..26].try_into().unwrap()), -2);
+    assert_eq!(top_down[54..57], [top_left.b, top_left.g, top_left.r]);
+
+    // 32-bit rows need no padding, and the masks are in a BITMAPV4HEADER
+    let bgra = bmp::encode(
+        &image,
+        BmpOptions {
+            top_down: false,
+            alpha: true,
+        },
+    );
+    assert_eq!(bgra.len(), 14 + 108 + 2 * 12);
+    assert_eq!(u32::from_le_bytes(bgra[14..18].try_into().unwrap()), 108);
+    assert_eq!(u16::from_le_bytes(bgra[28..30].try_into().unwrap()), 32);
+    assert_eq!(
+        bgra[122..126],
+        [bottom_left.b, bottom_left.g, bottom_left.r, 255]
+    );
+}
+
+#[test]
+fn test_decode_errors() {
+    let bmp = bmp::encode(&test_image(5, 3, true), BmpOptions::default());
+    assert_eq!(bmp::decode(&bmp[..bmp.len() - 1]), Err(BmpError::Truncated));
+    assert_eq!(bmp::decode(&bmp[..20]), Err(BmpError::Truncated));
+    assert_eq!(bmp::decode(b"PNG"), Err(BmpError::InvalidHeader));
+
+    // Run-length encoding
+    let mut compressed = bmp.clone();
+    compressed[30] = 1;
+    assert_eq!(bmp::decode(&compressed), Err(BmpError::Unsupported));
+}
+
+#[test]
+fn test_file_size_limits() {
+    assert_eq!(bmp::file_size(0, 0, BmpOptions::default()), Some(54));
+    assert_eq!(
+        bmp::file_size(1 << 16, 1 << 16, BmpOptions::default()),
+        None
+    );
+    assert_eq!(bmp::file_size(usize::MAX, 1, BmpOptions::default()), None);
+}
@@ -0,0 +1,97 @@
+use native::bytecode::Instr;
+use native::context::{Context, UnboundParameter};
+use native::lexer::Function;
+use native::parser;
+use num::Complex;
+
+const EXPRS: [&str; 6] = [
+    "z",
+    "3 + i",
+    "z (3 + i)",
+    "z^2 - 1 / z",
+    "sin(z) cosh(z) / (z + 2)",
+    "exp(sqrt(z) + Re(z) * Im(z)) - tan(z)^2",
+];
+
+#[test]
+fn test_program_matches_closure() {
+    for expr in EXPRS {
+        let program = parser::parse_to_program(expr).unwrap();
+        let closure = parser::parse_to_fn(expr).unwrap();
+        let mut regs = Vec::new();
+        for (re, im) in [(0.5, 0.5), (-1.25, 2.0), (3.0, -0.1)] {
+            let z = Complex::new(re, im);
+            assert_eq!(
+                program.eval_with(z, &[], &mut regs),
+                closure(z),
+                "{expr} at {z}"
+            );
+            assert_eq!(program.eval(z, &[]), closure(z), "{expr} at {z}");
+        }
+    }
+}
+
+#[test]
+fn test_program_layout() {
+    let program = parser::parse_to_program("z (3 + i)").unwrap();
+    assert_eq!(
+        program.instrs(),
+        &[
+            Instr::Var,
+            Instr::Const(Complex::new(3.0, 0.0)),
+            Instr::Const(Complex::new(0.0, 1.0)),
+            Instr::Add(1, 2),
+            Instr::Mult(0, 3),
+        ]
+    );
+    assert_eq!(program.len(), 5);
+}
+
+#[test]
+fn test_program_params() {
+    let program = parser::parse_to_program("z z + c + a c").unwrap();
+    assert_eq!(program.params(), &["c".to_string(), "a".to_string()]);
+
+    let mut ctx = Context::new();
+    ctx.set("c", Complex::new(1.0, -1.0));
+    assert_eq!(
+        program.bind(&ctx),
+        Err(UnboundParameter {
+            name: "a".to_string()
+        })
+    );
+
+    ctx.set("a", Complex::new(2.0, 0.0));
+    let params = program.bind(&ctx).unwrap();
+    let z = Complex::new(0.5, 2.0);
+    let expected = z * z + Complex::new(3.0, -3.0);
+    assert_eq!(program.eval(z, &params), expected);
+    assert_eq!(
+        parser::parse("z z + c + a c")
+            .unwrap()
+            .to_closure_with(&ctx)(z),
+        expected
+    );
+
+    // Rebinding changes the result without recompiling
+    ctx.set("c", Complex::new(0.0, 0.0));
+    let params = program.bind(&ctx).unwrap();
+    assert_eq!(program.eval(z, &params), z * z);
+}
+
+#[test]
+fn test_common_subexpressions() {
+    let program = parser::parse_to_program("sin(z) + sin(z) * (z + 1) / (1 + z)").unwrap();
+    assert_eq!(
+        program.instrs(),
+        &[
+            Instr::Var,
+            Instr::Fun(Function::Sin, 0),
+            Instr::Const(Complex::new(1.0, 0.0)),
+            Instr::Add(0, 2),
+            Instr::Mult(1, 3),
+            Instr::Div(4, 3),
+            Instr::Add(1, 5),
+        ]
+    );
+}
@@ -0,0 +1,143 @@
+use native::color::{ColorScheme, Grayscale, Hsv, ModulusMap, Oklch, Phase, Rgb, Scheme};
+use native::domain_color::{self, DCOptions};
+
+fn channels(rgb: Rgb) -> (u8, u8, u8) {
+    (rgb.r, rgb.g, rgb.b)
+}
+
+#[test]
+fn test_hsv() {
+    assert_eq!(channels(Hsv.color(0.0, 1.0)), (255, 0, 0));
+    assert_eq!(channels(Hsv.color(120.0, 1.0)), (0, 255, 0));
+    assert_eq!(channels(Hsv.color(240.0, 1.0)), (0, 0, 255));
+    assert_eq!(channels(Hsv.color(240.0, 0.0)), (0, 0, 0));
+}
+
+#[test]
+fn test_grayscale_ignores_hue() {
+    for hue in [0.0, 90.0, 200.0, 359.0] {
+        let (r, g, b) = channels(Grayscale.color(hue, 0.5));
+        assert_eq!((r, g), (g, b));
+        assert_eq!(r, 128);
+    }
+}
+
+#[test]
+fn test_from_linear_clamps() {
+    assert_eq!(channels(Rgb::from_linear(0.5, 1.5, -0.2)), (128, 255, 0));
+    assert_eq!(
+        channels(Rgb::from_linear(0.999, 0.001, f64::NAN)),
+        (255, 0, 0)
+    );
+    assert_eq!(
+        channels(Rgb::from_linear(f64::INFINITY, f64::NEG_INFINITY, 0.1)),
+        (255, 0, 26)
+    );
+}
+
+#[test]
+fn test_phase_ignores_lightness() {
+    assert_eq!(
+        channels(Phase.color(45.0, 0.0)),
+        channels(Phase.color(45.0, 1.0))
+    );
+}
+
+#[test]
+fn test_oklch_extremes() {
+    assert_eq!(channels(Oklch.color(10.0, 0.0)), (0, 0, 0));
+    let (r, g, b) = channels(Oklch.color(10.0, 1.0));
+    assert!(r >= 254 && g >= 254 && b >= 254);
+}
+
+#[test]
+fn test_scheme_option() {
+    let options = |scheme| DCOptions {
+        scheme,
+        ..Default::default()
+    };
+    let gray = domain_color::color_bmp(16, 16, "z", options(Scheme::Grayscale)).unwrap();
+    let header_size = 0x36;
+    for pixel in gray[header_size..header_size + 3 * 16 * 16].chunks_exact(3) {
+        assert!(pixel[0] == pixel[1] && pixel[1] == pixel[2]);
+    }
+    let hsv = domain_color::color_bmp(16, 16, "z", options(Scheme::Hsv)).unwrap();
+    assert_ne!(gray, hsv);
+}
+
+#[test]
+fn test_linear_light() {
+    let black = Rgb { r: 0, g: 0, b: 0 };
+    let white = Rgb {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    assert_eq!(white.to_linear_light(), [1.0; 3]);
+    // Mixing black and white evenly in linear light is lighter than mid-gray bytes
+    let mix = [0, 1, 2].map(|c| 0.5 * (black.to_linear_light()[c] + white.to_linear_light()[c]));
+    assert_eq!(
+        Rgb::from_linear_light(mix),
+        Rgb {
+            r: 188,
+            g: 188,
+            b: 188
+        }
+    );
+    for byte in [0, 1, 10, 54, 128, 200, 254, 255] {
+        let rgb = Rgb {
+            r: byte,
+            g: byte,
+            b: byte,
+        };
+        assert_eq!(Rgb::from_linear_light(rgb.to_linear_light()), rgb);
+    }
+}
+
+#[test]
+fn test_modulus_maps() {
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+    let rational = ModulusMap::Rational;
+    assert!(close(rational.lightness(1.0, 0.0), 0.5));
+    assert!(close(rational.lightness(3.0, 0.0), 0.75));
+    assert_eq!(rational.lightness(f64::INFINITY, 0.0), 1.0);
+
+    let squared = ModulusMap::Exponent { exponent: 2.0 };
+    assert!(close(squared.lightness(3.0, 0.0), 0.9));
+    assert!(close(squared.lightness(1.0, 0.0), 0.5));
+    assert_eq!(squared.lightness(1e200, 0.0), 1.0);
+
+    assert!(close(ModulusMap::Arctan.lightness(1.0, 0.0), 0.5));
+
+    // Bands repeat with each power of the base
+    let sawtooth = ModulusMap::LogSawtooth { base: 2.0 };
+    for modulus in [0.3, 1.5, 5.0] {
+        assert!(close(
+            sawtooth.lightness(modulus, 0.0),
+            sawtooth.lightness(4.0 * modulus, 0.0)
+        ));
+    }
+    assert!(sawtooth.lightness(1.1, 0.0) < sawtooth.lightness(1.9, 0.0));
+
+    let enhanced = ModulusMap::Enhanced {
+        base: 2.0,
+        phase_sectors: 4,
+    };
+    assert!(close(
+        enhanced.lightness(1.5, 10.0),
+        enhanced.lightness(3.0, 100.0)
+    ));
+    assert!(enhanced.lightness(1.5, 10.0) < enhanced.lightness(1.5, 80.0));
+    let modulus_only = ModulusMap::Enhanced {
+        base: 2.0,
+        phase_sectors: 0,
+    };
+    assert_eq!(
+        modulus_only.lightness(1.5, 10.0),
+        modulus_only.lightness(1.5, 80.0)
+    );
+
+    assert!(!ModulusMap::LogSawtooth { base: 1.0 }.is_valid());
+    assert!(!ModulusMap::Exponent { exponent: 0.0 }.is_valid());
+    assert!(!ModulusMap::Exponent { exponent: f64::NAN }.is_valid());
+}
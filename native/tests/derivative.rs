@@ -0,0 +1,94 @@
+use native::parser;
+use num::Complex;
+
+const POINTS: [Complex<f64>; 4] = [
+    Complex::new(0.7, 0.3),
+    Complex::new(-1.2, 0.8),
+    Complex::new(0.4, -1.1),
+    Complex::new(1.6, 0.2),
+];
+
+/// Compares the symbolic derivative against a central difference
+fn check(fun_str: &str) {
+    let node = parser::parse(fun_str).unwrap();
+    let f = node.clone().to_closure();
+    let df = node.derivative().to_closure();
+    let h = 1e-6;
+    for z in POINTS {
+        let numeric = (f(z + h) - f(z - h)) / (2.0 * h);
+        let symbolic = df(z);
+        assert!(
+            (symbolic - numeric).norm() <= 1e-6 * (1.0 + numeric.norm()),
+            "d/dz {fun_str} at {z}: symbolic {symbolic}, numeric {numeric}"
+        );
+    }
+}
+
+#[test]
+fn test_polynomial() {
+    let df = parser::parse("z^3 - 4z + 2")
+        .unwrap()
+        .derivative()
+        .to_closure();
+    assert_eq!(df(Complex::new(2.0, 0.0)), Complex::new(8.0, 0.0));
+    check("3z^4 - 2z^2 + z - 7");
+}
+
+#[test]
+fn test_rules() {
+    check("(z + 1) / (z - 2i)");
+    check("z sin(z)");
+    check("1 - exp(z) / z^2");
+    check("z^z");
+    check("2^z");
+    check("z^(1 + i)");
+    check("sin(z)^cos(z)");
+}
+
+#[test]
+fn test_functions() {
+    for fun in [
+        "sqrt", "exp", "sin", "cos", "tan", "cot", "sec", "csc", "sinh", "cosh", "tanh", "coth",
+        "sech", "csch", "ln", "log", "asin", "acos", "atan", "asinh", "acosh", "atanh", "gamma",
+    ] {
+        check(&format!("{fun}(z^2 + 1)"));
+    }
+    check("pow(z, 3)");
+    check("log(z, 2 + z)");
+    check("atan2(z, z^2 + 1)");
+}
+
+#[test]
+fn test_non_holomorphic() {
+    for fun in ["Re", "Im", "abs", "arg", "conj", "zeta"] {
+        let df = parser::parse(&format!("{fun}(z)"))
+            .unwrap()
+            .derivative()
+            .to_closure();
+        assert!(df(Complex::new(1.0, 1.0)).is_nan(), "{fun}");
+    }
+}
+
+#[test]
+fn test_constant_arguments() {
+    use native::context::Context;
+
+    // Parameters aren't folded away, so their functions must still have a
+    // derivative of 0, even where the function itself has none
+    let mut ctx = Context::default();
+    ctx.set("c", Complex::new(0.0, 0.0));
+    let z = Complex::new(0.7, 0.3);
+    for (fun_str, expected) in [
+        ("z + abs(c)", Complex::new(1.0, 0.0)),
+        ("z * Re(c + 2)", Complex::new(2.0, 0.0)),
+        ("z + ln(c)", Complex::new(1.0, 0.0)),
+        ("z + zeta(c) + log(c, 2)", Complex::new(1.0, 0.0)),
+        ("z^2 + conj(c) z", Complex::new(1.4, 0.6)),
+    ] {
+        let df = parser::parse(fun_str)
+            .unwrap()
+            .derivative()
+            .to_closure_with(&ctx);
+        assert_eq!(df(z), expected, "d/dz {fun_str}");
+    }
+}
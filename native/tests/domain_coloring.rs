@@ -1,4 +1,9 @@
-use native::domain_color::{self, DCOptions};
+use native::color::{ColorScheme, ModulusMap, Rgb, Scheme};
+use native::domain_color::{
+    self, DCOptions, MetadataError, Param, PngMetadata, RenderError, Viewport, MAX_DERIVATIVE,
+};
+use native::image::Image;
+use num::Complex;
 use std::io::Write;
 use tempfile::Builder;
 
@@ -14,12 +19,11 @@ fn color_bmp_test() {
         500,
         "z",
         DCOptions {
-            xmin: -5.0,
-            xmax: 5.0,
-            ymin: -5.0,
-            ymax: 5.0,
+            viewport: Viewport::new(-5.0, 5.0, -5.0, 5.0),
+            ..Default::default()
         },
-    );
+    )
+    .unwrap();
 
     img_file
         .write_all(&bmp)
@@ -28,3 +32,489 @@ fn color_bmp_test() {
     println!("{:?}", img_file.path());
     img_file.keep().expect("Could not save file");
 }
+
+#[test]
+fn color_bmp_errors() {
+    let options = || DCOptions {
+        viewport: Viewport::new(-1.0, 1.0, -1.0, 1.0),
+        ..Default::default()
+    };
+    assert!(matches!(
+        domain_color::color_bmp(10, 10, "z +", options()),
+        Err(RenderError::Parse(_))
+    ));
+    assert!(matches!(
+        domain_color::color_bmp(10, 10, "z + c", options()),
+        Err(RenderError::UnboundParameter(_))
+    ));
+    assert!(domain_color::color_bmp(
+        10,
+        10,
+        "z + c",
+        DCOptions {
+            params: vec![Param {
+                name: "c".to_string(),
+                re: 1.0,
+                im: 0.5
+            }],
+            ..options()
+        }
+    )
+    .is_ok());
+    assert_eq!(
+        domain_color::color_bmp(0, 10, "z", options()),
+        Err(RenderError::InvalidDimensions {
+            width: 0,
+            height: 10
+        })
+    );
+    assert_eq!(
+        domain_color::color_bmp(10, 0, "z", options()),
+        Err(RenderError::InvalidDimensions {
+            width: 10,
+            height: 0
+        })
+    );
+    assert_eq!(
+        domain_color::color_bmp(
+            10,
+            10,
+            "z",
+            DCOptions {
+                viewport: Viewport::new(1.0, -1.0, -1.0, 1.0),
+                ..options()
+            }
+        ),
+        Err(RenderError::InvalidXRange {
+            xmin: 1.0,
+            xmax: -1.0
+        })
+    );
+    assert!(matches!(
+        domain_color::color_bmp(
+            10,
+            10,
+            "z",
+            DCOptions {
+                viewport: Viewport::new(-1.0, 1.0, -1.0, f64::NAN),
+                ..options()
+            }
+        ),
+        Err(RenderError::InvalidYRange { .. })
+    ));
+}
+
+#[test]
+fn color_bmp_contours() {
+    let plain = domain_color::color_bmp(64, 64, "z^2 - 1", DCOptions::default()).unwrap();
+    for options in [
+        DCOptions {
+            modulus_contours: Some(2.0),
+            ..Default::default()
+        },
+        DCOptions {
+            phase_contours: 6,
+            ..Default::default()
+        },
+        DCOptions {
+            grid_spacing: Some(1.0),
+            line_width: 2.0,
+            ..Default::default()
+        },
+    ] {
+        let lined = domain_color::color_bmp(64, 64, "z^2 - 1", options).unwrap();
+        assert_ne!(plain, lined);
+        // Lines only ever darken the underlying coloring
+        assert!(plain.iter().zip(&lined).skip(0x36).all(|(p, l)| l <= p));
+    }
+    assert_eq!(
+        domain_color::color_bmp(
+            8,
+            8,
+            "z",
+            DCOptions {
+                modulus_contours: Some(1.0),
+                ..Default::default()
+            }
+        ),
+        Err(RenderError::InvalidContours)
+    );
+}
+
+#[test]
+fn color_bmp_derivative() {
+    let derivative = |order| DCOptions {
+        derivative: order,
+        ..Default::default()
+    };
+    // The derivative of z^2 + z is 2z + 1
+    assert_eq!(
+        domain_color::color_bmp(32, 32, "z^2 + z", derivative(1)),
+        domain_color::color_bmp(32, 32, "2z + 1", derivative(0))
+    );
+    assert_ne!(
+        domain_color::color_bmp(32, 32, "z^2 + z", derivative(1)),
+        domain_color::color_bmp(32, 32, "z^2 + z", derivative(0))
+    );
+
+    // The highest order stays tractable for expressions that grow under the
+    // product and quotient rules
+    assert!(domain_color::color_bmp(
+        8,
+        8,
+        "tan(z) exp(sin(z)) / (z^2 + 1)",
+        derivative(MAX_DERIVATIVE)
+    )
+    .is_ok());
+    assert_eq!(
+        domain_color::color_bmp(32, 32, "z", derivative(MAX_DERIVATIVE + 1)),
+        Err(RenderError::InvalidDerivative(MAX_DERIVATIVE + 1))
+    );
+}
+
+#[test]
+fn render_matches_bmp() {
+    let options = || DCOptions {
+        phase_contours: 4,
+        ..Default::default()
+    };
+    let image = domain_color::render(16, 12, "z^3 - 1", &options()).unwrap();
+    assert_eq!((image.width, image.height), (16, 12));
+    assert_eq!(image.pixels.len(), 4 * 16 * 12);
+    assert!(image.pixels.chunks_exact(4).all(|pixel| pixel[3] == 255));
+
+    // The BMP stores the same pixels bottom up, as BGR
+    let bmp = domain_color::color_bmp(16, 12, "z^3 - 1", options()).unwrap();
+    let offset = 0x36;
+    for (y, x) in [(0, 0), (5, 7), (11, 15)] {
+        let rgb = image.pixel(x, y);
+        let idx = offset + 3 * ((11 - y) * 16 + x);
+        assert_eq!([rgb.b, rgb.g, rgb.r], bmp[idx..idx + 3]);
+    }
+}
+
+#[test]
+fn color_png_metadata() {
+    let options = || DCOptions {
+        viewport: Viewport::new(-2.5, 0.1, -5.0, 5.0),
+        preserve_aspect: false,
+        params: vec![
+            Param {
+                name: "a".to_string(),
+                re: 1.0 / 3.0,
+                im: -2.0,
+            },
+            Param {
+                name: "unused".to_string(),
+                re: 0.0,
+                im: 0.0,
+            },
+        ],
+        derivative: 1,
+        ..Default::default()
+    };
+    let png = domain_color::color_png(20, 10, "a z^2", options(), true).unwrap();
+    let decoder = png::Decoder::new(png.as_slice());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    let image = domain_color::render(20, 10, "a z^2", &options()).unwrap();
+    assert_eq!(pixels, image.pixels);
+
+    assert_eq!(
+        PngMetadata::from_png(&png),
+        Ok(PngMetadata {
+            expression: "a z^2".to_string(),
+            viewport: Viewport::new(-2.5, 0.1, -5.0, 5.0),
+            params: vec![Param {
+                name: "a".to_string(),
+                re: 1.0 / 3.0,
+                im: -2.0,
+            }],
+            derivative: 1,
+        })
+    );
+
+    let bare = domain_color::color_png(20, 10, "a z^2", options(), false).unwrap();
+    assert_eq!(
+        PngMetadata::from_png(&bare),
+        Err(MetadataError::Missing("Expression"))
+    );
+    // Untrusted images can't request derivatives of any order
+    let text = |derivative: &str| {
+        [
+            ("Expression", "z"),
+            ("Viewport", "-1 1 -1 1"),
+            ("Parameters", ""),
+            ("Derivative", derivative),
+        ]
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+    };
+    let png = Image::new(1, 1).to_png(&text("1000000")).unwrap();
+    assert_eq!(
+        PngMetadata::from_png(&png),
+        Err(MetadataError::Invalid("Derivative"))
+    );
+    let png = Image::new(1, 1).to_png(&text("2")).unwrap();
+    assert_eq!(PngMetadata::from_png(&png).unwrap().derivative, 2);
+    assert!(matches!(
+        PngMetadata::from_png(&[0; 16]),
+        Err(MetadataError::Decoding(_))
+    ));
+}
+
+#[test]
+fn render_into_buffer() {
+    let options = DCOptions::default();
+    let image = domain_color::render(7, 5, "1 / z", &options).unwrap();
+    // Stale contents are overwritten, alpha included
+    let mut pixels = vec![17; 4 * 7 * 5];
+    domain_color::render_into(7, 5, "1 / z", &options, &mut pixels).unwrap();
+    assert_eq!(pixels, image.pixels);
+    assert_eq!(
+        domain_color::render_into(7, 5, "1 / z", &options, &mut pixels[1..]),
+        Err(RenderError::InvalidBufferSize {
+            expected: 140,
+            found: 139
+        })
+    );
+}
+
+#[test]
+fn asymmetric_viewport() {
+    // Each pixel is colored by the value at its center, so with the identity
+    // the corners of an offset, non-square viewport show its corner values
+    let viewport = Viewport::new(1.0, 9.0, -0.5, 1.5);
+    let options = |preserve_aspect| DCOptions {
+        viewport,
+        preserve_aspect,
+        scheme: Scheme::Grayscale,
+        ..Default::default()
+    };
+    let (width, height) = (40, 10);
+    let stretched = domain_color::render(width, height, "z", &options(false)).unwrap();
+    // Pixels are 0.2 units wide and high
+    for (x, y, corner) in [
+        (0, 0, Complex::new(1.1, 1.4)),
+        (39, 0, Complex::new(8.9, 1.4)),
+        (0, 9, Complex::new(1.1, -0.4)),
+        (39, 9, Complex::new(8.9, -0.4)),
+    ] {
+        let expected = Scheme::Grayscale.color(0.0, corner.norm() / (corner.norm() + 1.0));
+        assert_eq!(stretched.pixel(x, y), expected, "pixel ({x}, {y})");
+    }
+
+    // Preserving the aspect ratio widens the y range about its center
+    let fitted = options(true).effective_viewport(width, 2 * height);
+    for (found, expected) in [
+        (fitted.xmin, 1.0),
+        (fitted.xmax, 9.0),
+        (fitted.ymin, -1.5),
+        (fitted.ymax, 2.5),
+    ] {
+        assert!((found - expected).abs() < 1e-12, "{fitted:?}");
+    }
+    let square = domain_color::render(width, 2 * height, "z", &options(true)).unwrap();
+    assert_ne!(
+        square,
+        domain_color::render(width, 2 * height, "z", &options(false)).unwrap()
+    );
+    assert_eq!(
+        square,
+        domain_color::render(
+            width,
+            2 * height,
+            "z",
+            &DCOptions {
+                viewport: fitted,
+                ..options(false)
+            }
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn supersampling() {
+    let fun = "exp(1 / z)";
+    let options = |supersampling, jitter| DCOptions {
+        viewport: Viewport::new(-0.5, 0.5, -0.25, 0.25),
+        preserve_aspect: false,
+        supersampling,
+        jitter,
+        ..Default::default()
+    };
+    let single = domain_color::render(40, 20, fun, &options(1, false)).unwrap();
+    assert_eq!(
+        single,
+        domain_color::render(
+            40,
+            20,
+            fun,
+            &DCOptions {
+                viewport: Viewport::new(-0.5, 0.5, -0.25, 0.25),
+                preserve_aspect: false,
+                ..Default::default()
+            }
+        )
+        .unwrap()
+    );
+
+    // A 2x2 grid samples the pixel centers of an image twice as large, averaged in linear light
+    let grid = domain_color::render(40, 20, fun, &options(2, false)).unwrap();
+    let large = domain_color::render(80, 40, fun, &options(1, false)).unwrap();
+    for y in 0..20 {
+        for x in 0..40 {
+            let mut sum = [0.0; 3];
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let linear = large.pixel(2 * x + dx, 2 * y + dy).to_linear_light();
+                (0..3).for_each(|c| sum[c] += linear[c] / 4.0);
+            }
+            let expected = Rgb::from_linear_light(sum);
+            let found = grid.pixel(x, y);
+            assert!(
+                expected.r.abs_diff(found.r) <= 1
+                    && expected.g.abs_diff(found.g) <= 1
+                    && expected.b.abs_diff(found.b) <= 1,
+                "pixel ({x}, {y}): {found:?} instead of {expected:?}"
+            );
+        }
+    }
+    assert_ne!(grid, single);
+
+    // Jittered samples are reproducible
+    let jittered = domain_color::render(40, 20, fun, &options(4, true)).unwrap();
+    assert_eq!(
+        jittered,
+        domain_color::render(40, 20, fun, &options(4, true)).unwrap()
+    );
+    assert_ne!(
+        jittered,
+        domain_color::render(40, 20, fun, &options(4, false)).unwrap()
+    );
+
+    assert_eq!(
+        domain_color::render(40, 20, fun, &options(0, false)),
+        Err(RenderError::InvalidSupersampling(0))
+    );
+
+    // Contour lines are averaged over the samples too. The grid line Re z = 0
+    // runs through the centers of column 4, so it only covers the samples of
+    // the left half of column 3.
+    let grid = |supersampling, grid_spacing| DCOptions {
+        viewport: Viewport::new(-1.125, 0.875, -0.5, 0.5),
+        preserve_aspect: false,
+        grid_spacing,
+        supersampling,
+        ..Default::default()
+    };
+    for (supersampling, covered) in [(1, false), (4, true)] {
+        let lines = domain_color::render(8, 4, "z", &grid(supersampling, Some(1.0))).unwrap();
+        let fill = domain_color::render(8, 4, "z", &grid(supersampling, None)).unwrap();
+        assert_ne!(lines.pixel(4, 0), fill.pixel(4, 0));
+        assert_eq!(lines.pixel(3, 0) != fill.pixel(3, 0), covered);
+    }
+}
+
+#[test]
+fn adaptive_sampling() {
+    let fun = "exp(1 / z)";
+    let options = |supersampling, adaptive| DCOptions {
+        viewport: Viewport::new(-1.0, 1.0, -0.5, 0.5),
+        preserve_aspect: false,
+        supersampling,
+        adaptive,
+        ..Default::default()
+    };
+    let single = domain_color::render(80, 40, fun, &options(1, false)).unwrap();
+    let uniform = domain_color::render(80, 40, fun, &options(4, false)).unwrap();
+    let adaptive = domain_color::render(80, 40, fun, &options(4, true)).unwrap();
+
+    let distance = |a: &Image, b: &Image| {
+        let diff = a.pixels.iter().zip(&b.pixels);
+        diff.map(|(a, b)| a.abs_diff(*b) as u64).sum::<u64>()
+    };
+    let pixels_equal = |a: &Image, b: &Image| {
+        let pixels = a.pixels.chunks_exact(4).zip(b.pixels.chunks_exact(4));
+        pixels.filter(|(a, b)| a == b).count()
+    };
+    // Far from the essential singularity the function is smooth enough to
+    // sample once per pixel, while the pixels near it are refined
+    let refined = 80 * 40 - pixels_equal(&adaptive, &single);
+    assert!(
+        refined > 0 && refined < 80 * 40 / 2,
+        "{refined} pixels refined"
+    );
+    assert!(4 * distance(&adaptive, &uniform) < distance(&single, &uniform));
+
+    // Without supersampling there is nothing to refine
+    assert_eq!(
+        domain_color::render(80, 40, fun, &options(1, true)).unwrap(),
+        single
+    );
+}
+
+#[test]
+fn special_values() {
+    // The center pixel of a 3x3 image of this viewport is at the origin
+    let options = DCOptions {
+        viewport: Viewport::new(-1.5, 1.5, -1.5, 1.5),
+        ..Default::default()
+    };
+    let origin = |fun: &str, options: &DCOptions| {
+        domain_color::render(3, 3, fun, options)
+            .unwrap()
+            .pixel(1, 1)
+    };
+    assert_eq!(origin("1 / z", &options), options.infinity_color);
+    assert_eq!(origin("1 / z^2", &options), options.infinity_color);
+    assert_eq!(origin("z", &options), options.zero_color);
+    assert_eq!(origin("z / z", &options), options.nan_color);
+    // The modulus overflows although both parts are finite
+    assert_eq!(origin("1.5 * 10^308 (1 + i) + z", &options).b, 255);
+
+    let options = DCOptions {
+        zero_color: Rgb { r: 1, g: 2, b: 3 },
+        infinity_color: Rgb { r: 4, g: 5, b: 6 },
+        nan_color: Rgb { r: 7, g: 8, b: 9 },
+        ..options
+    };
+    assert_eq!(origin("1 / z", &options), Rgb { r: 4, g: 5, b: 6 });
+    assert_eq!(origin("sin(z)", &options), Rgb { r: 1, g: 2, b: 3 });
+    assert_eq!(origin("0 / z", &options), Rgb { r: 7, g: 8, b: 9 });
+}
+
+#[test]
+fn modulus_map() {
+    let options = |modulus_map| DCOptions {
+        viewport: Viewport::new(-2.0, 2.0, -2.0, 2.0),
+        scheme: Scheme::Grayscale,
+        modulus_map,
+        ..Default::default()
+    };
+    let render = |modulus_map| domain_color::render(4, 4, "z", &options(modulus_map));
+    assert_eq!(render(ModulusMap::Rational), render(ModulusMap::default()));
+
+    // The pixel at column 3 and row 0 is centered on 1.5 + 1.5i
+    let z = Complex::new(1.5, 1.5);
+    for map in [
+        ModulusMap::Arctan,
+        ModulusMap::Exponent { exponent: 0.5 },
+        ModulusMap::LogSawtooth { base: 3.0 },
+        ModulusMap::Enhanced {
+            base: 2.0,
+            phase_sectors: 8,
+        },
+    ] {
+        let expected = Scheme::Grayscale.color(45.0, map.lightness(z.norm(), 45.0));
+        assert_eq!(render(map).unwrap().pixel(3, 0), expected, "{map:?}");
+    }
+
+    assert_eq!(
+        render(ModulusMap::Enhanced {
+            base: 0.5,
+            phase_sectors: 8
+        }),
+        Err(RenderError::InvalidModulusMap)
+    );
+}
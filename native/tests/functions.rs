@@ -0,0 +1,182 @@
+use native::parser;
+use native::special;
+use num::Complex;
+use std::f64::consts::PI;
+
+const POINTS: [Complex<f64>; 6] = [
+    Complex::new(0.5, 0.0),
+    Complex::new(-1.5, 0.25),
+    Complex::new(0.3, -2.0),
+    Complex::new(-0.7, -0.9),
+    Complex::new(2.0, 1.0),
+    Complex::new(-4.0, 0.0),
+];
+
+fn assert_close(actual: Complex<f64>, expected: Complex<f64>, fun_str: &str, z: Complex<f64>) {
+    let tol = 1e-12 * (1.0 + expected.norm());
+    assert!(
+        (actual - expected).norm() <= tol,
+        "{fun_str} at {z}: got {actual}, expected {expected}"
+    );
+}
+
+fn check(fun_str: &str, reference: fn(Complex<f64>) -> Complex<f64>) {
+    let f = parser::parse(fun_str).unwrap().to_closure();
+    for z in POINTS {
+        assert_close(f(z), reference(z), fun_str, z);
+    }
+}
+
+#[test]
+fn test_elementary() {
+    check("sqrt(z)", |z| z.sqrt());
+    check("exp(z)", |z| z.exp());
+}
+
+#[test]
+fn test_trig() {
+    check("sin(z)", |z| z.sin());
+    check("cos(z)", |z| z.cos());
+    check("tan(z)", |z| z.tan());
+    check("cot(z)", |z| z.tan().inv());
+    check("sec(z)", |z| z.cos().inv());
+    check("csc(z)", |z| z.sin().inv());
+}
+
+#[test]
+fn test_hyperbolic() {
+    check("sinh(z)", |z| z.sinh());
+    check("cosh(z)", |z| z.cosh());
+    check("tanh(z)", |z| z.tanh());
+    check("coth(z)", |z| z.tanh().inv());
+    check("sech(z)", |z| z.cosh().inv());
+    check("csch(z)", |z| z.sinh().inv());
+}
+
+#[test]
+fn test_real_imag() {
+    check("Re(z)", |z| Complex::new(z.re, 0.0));
+    check("Im(z)", |z| Complex::new(z.im, 0.0));
+}
+
+#[test]
+fn test_sqrt_branch_cut() {
+    let f = parser::parse("sqrt(z)").unwrap().to_closure();
+    // Principal branch: the cut lies on the negative real axis
+    assert_close(
+        f(Complex::new(-4.0, 0.0)),
+        Complex::new(0.0, 2.0),
+        "sqrt(z)",
+        Complex::new(-4.0, 0.0),
+    );
+    assert!(f(Complex::new(-4.0, -1e-9)).im < 0.0);
+    assert!(f(Complex::new(-4.0, 1e-9)).im > 0.0);
+}
+
+#[test]
+fn test_composition() {
+    check("exp(sin(z)) + 2", |z| z.sin().exp() + 2.0);
+}
+
+#[test]
+fn test_multi_argument() {
+    check("pow(z, 3)", |z| z.powc(Complex::new(3.0, 0.0)));
+    check("pow(2, z)", |z| Complex::new(2.0, 0.0).powc(z));
+    check("log(z)", |z| z.ln());
+    check("log(z, 2)", |z| z.ln() / 2f64.ln());
+    check("log(z, i)", |z| z.ln() / Complex::i().ln());
+
+    // atan2 agrees with the real version for real arguments
+    let f = parser::parse("atan2(Im(z), Re(z))").unwrap().to_closure();
+    for z in POINTS {
+        assert_close(f(z), Complex::new(z.im.atan2(z.re), 0.0), "atan2", z);
+    }
+}
+
+#[test]
+fn test_user_defined() {
+    check("f(w) = w^2 + 1; f(z) / f(z + 1)", |z| {
+        let f = |w: Complex<f64>| w.powc(Complex::new(2.0, 0.0)) + 1.0;
+        f(z) / f(z + 1.0)
+    });
+    check("g(a, b) = a - 2b; h(w) = g(w, i) sin(w); h(z + 1)", |z| {
+        let w = z + 1.0;
+        (w - 2.0 * Complex::i()) * w.sin()
+    });
+}
+
+#[test]
+fn test_inverse() {
+    check("ln(z)", |z| z.ln());
+    check("asin(z)", |z| z.asin());
+    check("acos(z)", |z| z.acos());
+    check("atan(z)", |z| z.atan());
+    check("asinh(z)", |z| z.asinh());
+    check("acosh(z)", |z| z.acosh());
+    check("atanh(z)", |z| z.atanh());
+    check("sin(asin(z))", |z| z);
+}
+
+#[test]
+fn test_abs_arg_conj() {
+    check("abs(z)", |z| Complex::new(z.norm(), 0.0));
+    check("arg(z)", |z| Complex::new(z.arg(), 0.0));
+    check("conj(z)", |z| z.conj());
+    check("abs(z) exp(i arg(z))", |z| z);
+}
+
+fn assert_approx(actual: Complex<f64>, expected: Complex<f64>, tol: f64) {
+    assert!(
+        (actual - expected).norm() <= tol * (1.0 + expected.norm()),
+        "got {actual}, expected {expected}"
+    );
+}
+
+#[test]
+fn test_gamma() {
+    let gamma = |re, im| special::gamma(Complex::new(re, im));
+    assert_approx(gamma(5.0, 0.0), Complex::new(24.0, 0.0), 1e-13);
+    assert_approx(gamma(0.5, 0.0), Complex::new(PI.sqrt(), 0.0), 1e-13);
+    assert_approx(gamma(-0.5, 0.0), Complex::new(-2.0 * PI.sqrt(), 0.0), 1e-13);
+    assert_approx(
+        gamma(1.0, 1.0),
+        Complex::new(0.498_015_668_118_356, -0.154_949_828_301_810_7),
+        1e-13,
+    );
+    // Γ(z + 1) = z Γ(z)
+    for z in POINTS.iter().filter(|z| z.im != 0.0 || z.re > 0.0) {
+        assert_approx(special::gamma(z + 1.0), z * special::gamma(*z), 1e-11);
+    }
+    check("gamma(z)", special::gamma);
+}
+
+#[test]
+fn test_zeta() {
+    let zeta = |re, im| special::zeta(Complex::new(re, im));
+    assert_approx(zeta(2.0, 0.0), Complex::new(PI * PI / 6.0, 0.0), 1e-12);
+    assert_approx(zeta(4.0, 0.0), Complex::new(PI.powi(4) / 90.0, 0.0), 1e-12);
+    assert_approx(zeta(0.0, 0.0), Complex::new(-0.5, 0.0), 1e-12);
+    assert_approx(zeta(-1.0, 0.0), Complex::new(-1.0 / 12.0, 0.0), 1e-12);
+    assert_approx(zeta(-2.0, 0.0), Complex::new(0.0, 0.0), 1e-12);
+    // First nontrivial zero
+    assert!(zeta(0.5, 14.134_725_141_734_693).norm() < 1e-9);
+    check("zeta(z)", special::zeta);
+}
+
+#[test]
+fn test_digamma() {
+    let euler_gamma = 0.577_215_664_901_532_9;
+    assert_approx(
+        special::digamma(Complex::new(1.0, 0.0)),
+        Complex::new(-euler_gamma, 0.0),
+        1e-13,
+    );
+    // ψ(z + 1) = ψ(z) + 1/z
+    for z in POINTS.iter().filter(|z| z.im != 0.0 || z.re > 0.0) {
+        assert_approx(
+            special::digamma(z + 1.0),
+            special::digamma(*z) + z.inv(),
+            1e-12,
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use native::domain_color::{self, DCOptions, RenderError};
+use native::job::{self, RenderJob};
+use std::time::Duration;
+
+const FUN: &str = "zeta(z) gamma(z / 2)";
+
+#[test]
+fn test_job_result() {
+    let options = DCOptions::default();
+    let job = job::start(64, 48, FUN.to_string(), options.clone());
+    let result = loop {
+        if let Some(result) = job.take_result() {
+            break result;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+    assert_eq!(
+        result.unwrap(),
+        domain_color::render(64, 48, FUN, &options).unwrap()
+    );
+    assert_eq!(job.progress(), 1.0);
+    assert!(job.take_result().is_none());
+
+    let failed = job::start(0, 48, FUN.to_string(), options);
+    while !failed.is_finished() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(matches!(
+        failed.take_result(),
+        Some(Err(RenderError::InvalidDimensions { .. }))
+    ));
+
+    // Dimensions whose pixel count overflows fail before any work is counted
+    let huge = job::start(usize::MAX, 2, FUN.to_string(), DCOptions::default());
+    assert!(huge.is_finished());
+    assert!(matches!(
+        huge.take_result(),
+        Some(Err(RenderError::InvalidDimensions { .. }))
+    ));
+}
+
+#[test]
+fn test_cancel() {
+    // Cancelled before starting, no pixel is colored
+    let job = RenderJob::new();
+    job.cancel();
+    assert_eq!(
+        job::render(32, 32, FUN, &DCOptions::default(), &job),
+        Err(RenderError::Cancelled)
+    );
+    assert_eq!(job.progress(), 0.0);
+
+    // Cancelled while running, the render stops before the end
+    let job = job::start(2000, 2000, FUN.to_string(), DCOptions::default());
+    while job.progress() == 0.0 {
+        std::thread::yield_now();
+    }
+    job.cancel();
+    while !job.is_finished() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(job.progress() < 1.0);
+    assert_eq!(job.take_result(), Some(Err(RenderError::Cancelled)));
+}
+
+#[test]
+fn test_progressive() {
+    let options = DCOptions {
+        phase_contours: 6,
+        ..Default::default()
+    };
+    let job = RenderJob::new();
+    let mut frames = Vec::new();
+    job::render_progressive(100, 60, FUN, &options, &job, |frame| frames.push(frame)).unwrap();
+    let sizes: Vec<_> = frames
+        .iter()
+        .map(|frame| (frame.scale, frame.image.width, frame.image.height))
+        .collect();
+    assert_eq!(sizes, [(8, 13, 8), (4, 25, 15), (2, 50, 30), (1, 100, 60)]);
+    assert_eq!(
+        frames[3].image,
+        domain_color::render(100, 60, FUN, &options).unwrap()
+    );
+    assert_eq!(job.progress(), 1.0);
+
+    // Tiny images skip passes that would not be any smaller
+    let mut scales = Vec::new();
+    job::render_progressive(3, 2, FUN, &options, &RenderJob::new(), |frame| {
+        scales.push(frame.scale)
+    })
+    .unwrap();
+    assert_eq!(scales, [4, 2, 1]);
+
+    // Cancelling from the first frame stops the render
+    let job = RenderJob::new();
+    let mut count = 0;
+    let result = job::render_progressive(100, 60, FUN, &options, &job, |_| {
+        count += 1;
+        job.cancel();
+    });
+    assert_eq!((result, count), (Err(RenderError::Cancelled), 1));
+}
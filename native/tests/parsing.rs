@@ -42,6 +42,45 @@ fn test_parser() {
     assert_eq!(tree.to_mermaid(), TREE_STR);
 }
 
+const DOT_STR: &str = "digraph {
+    0 [label=\"*\"];
+    1 [label=\"z\"];
+    2 [label=\"+\"];
+    3 [label=\"3+0i\"];
+    4 [label=\"0+1i\"];
+    0 -> 1;
+    0 -> 2;
+    2 -> 3;
+    2 -> 4;
+}";
+
+#[test]
+fn test_dot() {
+    let tree = parser::parse("z (3 + i)").unwrap();
+    assert_eq!(tree.to_dot(), DOT_STR);
+}
+
+#[test]
+fn test_json() {
+    let tree = parser::parse("a sin(z)^2 - log(z, 2) / -i").unwrap();
+    let json = tree.to_json();
+    assert_eq!(Node::from_json(&json).unwrap(), tree);
+
+    let leaf = Node::Binary {
+        op: Token::Add,
+        left: Some(Box::new(Node::Var)),
+        right: Some(Box::new(Node::Param {
+            name: "a".to_string(),
+        })),
+    };
+    assert_eq!(
+        leaf.to_json(),
+        r#"{"Binary":{"op":"Add","left":"Var","right":{"Param":{"name":"a"}}}}"#
+    );
+    assert!(Node::from_json(r#"{"Binary":{"op":"Add"}}"#).is_err());
+    assert!(Node::from_json(r#"{"Fun":{"fun":"Sin","args":[]}}"#).is_err());
+}
+
 #[test]
 fn test_closure() {
     println!("Testing parser...");
@@ -49,3 +88,212 @@ fn test_closure() {
     assert_eq!(f(Complex::new(1.0, 0.0)), Complex::new(3.0, 1.0));
     assert_eq!(f(Complex::new(2.0, 0.0)), Complex::new(6.0, 2.0));
 }
+
+#[test]
+fn test_closure_send_sync() {
+    fn assert_send_sync<T: Send + Sync + ?Sized>(_: &T) {}
+    let f = parser::parse_to_fn("sin(z) / z").unwrap();
+    assert_send_sync(&f);
+    std::thread::scope(|s| {
+        let handle = s.spawn(|| f(Complex::new(1.0, 0.0)));
+        assert_eq!(handle.join().unwrap(), Complex::new(1.0f64.sin(), 0.0));
+    });
+}
+
+#[test]
+fn test_parse_errors() {
+    use native::lexer::{LexError, Span};
+    use parser::{Expected, ParseError};
+
+    assert_eq!(
+        parser::parse("(z + 1").unwrap_err(),
+        ParseError::UnexpectedEnd {
+            span: Span { start: 6, end: 6 },
+            expected: Expected::ClosingParen,
+        }
+    );
+    assert_eq!(
+        parser::parse("z * )").unwrap_err(),
+        ParseError::UnexpectedToken {
+            found: Token::RParen,
+            span: Span { start: 4, end: 5 },
+            expected: Expected::Operand,
+        }
+    );
+    assert_eq!(
+        parser::parse("z + foo(z)").unwrap_err(),
+        ParseError::Lex {
+            err: LexError::UnknownIdentifier,
+            span: Span { start: 4, end: 7 },
+        }
+    );
+    assert_eq!(
+        parser::parse("z + foo $").unwrap_err(),
+        ParseError::Lex {
+            err: LexError::InvalidCharacter,
+            span: Span { start: 8, end: 9 },
+        }
+    );
+    assert_eq!(
+        parser::parse("sinn(z)").unwrap_err().span(),
+        Span { start: 0, end: 4 }
+    );
+    // A parameter right before a parenthesis reads as a call, so the error
+    // explains how to multiply instead
+    let err = parser::parse("c(z^2 + 1)").unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::Lex {
+            err: LexError::UnknownIdentifier,
+            span: Span { start: 0, end: 1 },
+        }
+    );
+    assert!(err.to_string().contains("c*(...)"), "{err}");
+    assert!(parser::parse("c (z^2 + 1)").is_ok());
+    assert!(parser::parse("c*(z^2 + 1)").is_ok());
+    assert_eq!(
+        parser::parse("1.2.3 z").unwrap_err(),
+        ParseError::Lex {
+            err: LexError::DoubleDecimal,
+            span: Span { start: 0, end: 5 },
+        }
+    );
+    assert_eq!(
+        parser::parse("z $").unwrap_err().span(),
+        Span { start: 2, end: 3 }
+    );
+    assert_eq!(
+        parser::parse("sin z").unwrap_err(),
+        ParseError::UnexpectedToken {
+            found: Token::VarZ,
+            span: Span { start: 4, end: 5 },
+            expected: Expected::OpeningParen,
+        }
+    );
+    assert_eq!(
+        parser::parse("(z))").unwrap_err().span(),
+        Span { start: 3, end: 4 }
+    );
+}
+
+#[test]
+fn test_parse_params() {
+    let tree = parser::parse("a z + alpha").unwrap();
+    assert_eq!(
+        tree.to_mermaid(),
+        "flowchart TD
+    0[+]
+    1[*]
+    2[alpha]
+    3[a]
+    4[z]
+    0 --> 1
+    0 --> 2
+    1 --> 3
+    1 --> 4"
+    );
+
+    // A parameter separated from a parenthesis multiplies it
+    let f = parser::parse_to_fn("a (z + 1)");
+    assert!(f.is_ok());
+}
+
+#[test]
+fn test_definition_errors() {
+    use native::lexer::Span;
+    use parser::{Expected, ParseError};
+
+    assert_eq!(
+        parser::parse("pow(z)").unwrap_err(),
+        ParseError::WrongArgumentCount {
+            span: Span { start: 0, end: 3 },
+            min: 2,
+            max: 2,
+            found: 1,
+        }
+    );
+    assert_eq!(
+        parser::parse("f(a, b) = a b; f(z)").unwrap_err(),
+        ParseError::WrongArgumentCount {
+            span: Span { start: 15, end: 16 },
+            min: 2,
+            max: 2,
+            found: 1,
+        }
+    );
+    assert_eq!(
+        parser::parse("log(z, 2, 3)").unwrap_err().span(),
+        Span { start: 0, end: 3 }
+    );
+    assert_eq!(
+        parser::parse("f(w) = w; f(w) = 2w; f(z)").unwrap_err(),
+        ParseError::DuplicateDefinition {
+            span: Span { start: 10, end: 11 }
+        }
+    );
+    assert_eq!(
+        parser::parse("f(w, w) = w; f(z, z)").unwrap_err(),
+        ParseError::DuplicateDefinition {
+            span: Span { start: 5, end: 6 }
+        }
+    );
+    assert_eq!(
+        parser::parse("f(w) = f(w - 1); f(z)").unwrap_err(),
+        ParseError::RecursiveDefinition {
+            span: Span { start: 0, end: 1 }
+        }
+    );
+    assert_eq!(
+        parser::parse("f(w) = w) f(z)").unwrap_err(),
+        ParseError::UnexpectedToken {
+            found: Token::RParen,
+            span: Span { start: 8, end: 9 },
+            expected: Expected::Semicolon,
+        }
+    );
+}
+
+#[test]
+fn test_definition_params() {
+    // Free parameters of a definition stay parameters of the expression
+    let program = parser::parse_to_program("f(w) = w + c; f(z) a").unwrap();
+    assert_eq!(program.params(), &["c".to_string(), "a".to_string()]);
+}
+
+#[test]
+fn test_definition_scope() {
+    use native::context::Context;
+
+    // The free parameter of f stays free when f is called where c is bound
+    let tree = parser::parse("f(w) = w + c; g(c) = f(1); g(5)").unwrap();
+    let mut ctx = Context::default();
+    ctx.set("c", Complex::new(10.0, 0.0));
+    let f = tree.clone().to_closure_with(&ctx);
+    assert_eq!(f(Complex::new(0.0, 0.0)), Complex::new(11.0, 0.0));
+    let program = parser::parse_to_program("f(w) = w + c; g(c) = f(1); g(5)").unwrap();
+    assert_eq!(program.params(), &["c".to_string()]);
+
+    // Arguments naming free parameters aren't substituted again
+    let tree = parser::parse("f(a, b) = a - b; f(b, a)").unwrap();
+    let mut ctx = Context::default();
+    ctx.set("a", Complex::new(1.0, 0.0));
+    ctx.set("b", Complex::new(3.0, 0.0));
+    let f = tree.to_closure_with(&ctx);
+    assert_eq!(f(Complex::new(0.0, 0.0)), Complex::new(2.0, 0.0));
+
+    // A parameter may shadow the name of the function
+    let f = parser::parse_to_fn("f(f) = f + 1; f(z)").unwrap();
+    assert_eq!(f(Complex::new(2.0, 0.0)), Complex::new(3.0, 0.0));
+}
+
+#[test]
+fn test_unary_minus() {
+    let f = parser::parse_to_fn("-z^2 + 2^-z - -1").unwrap();
+    let z = Complex::new(0.5, 1.5);
+    let expected = -z.powc(Complex::new(2.0, 0.0)) + Complex::new(2.0, 0.0).powc(-z) + 1.0;
+    assert_eq!(f(z), expected);
+
+    // Subtraction after an operand is still binary
+    let f = parser::parse_to_fn("z -1").unwrap();
+    assert_eq!(f(z), z - 1.0);
+}
@@ -0,0 +1,156 @@
+use native::ast::Node;
+use native::lexer::{Function, Token};
+use native::parser;
+use num::Complex;
+use proptest::prelude::*;
+
+/// Checks the printed forms of `expr` and that the infix form parses back to the same tree
+fn check(expr: &str, infix: &str, latex: &str) {
+    let tree = parser::parse(expr).unwrap();
+    assert_eq!(tree.to_infix(), infix, "{expr} as infix");
+    assert_eq!(tree.to_latex(), latex, "{expr} as LaTeX");
+    assert_eq!(parser::parse(infix).unwrap(), tree, "{expr} round trip");
+}
+
+#[test]
+fn test_minimal_parentheses() {
+    check("((z + 1)) + (2)", "z + 1 + 2", "z + 1 + 2");
+    check("z - (1 - z)", "z - (1 - z)", "z - \\left(1 - z\\right)");
+    check(
+        "(z * 2) / (z * 3)",
+        "z * 2 / (z * 3)",
+        "\\frac{z \\cdot 2}{z \\cdot 3}",
+    );
+    check("z^(2^z)", "z^2^z", "{z}^{{2}^{z}}");
+    check("(z^2)^z", "(z^2)^z", "{\\left({z}^{2}\\right)}^{z}");
+    check(
+        "(-z)^2 - -(z^2)",
+        "(-z)^2 - -z^2",
+        "{\\left(-z\\right)}^{2} - -{z}^{2}",
+    );
+    check("z^(-1)", "z^-1", "{z}^{-1}");
+    check(
+        "(z + 1)(z - 1)",
+        "(z + 1) * (z - 1)",
+        "\\left(z + 1\\right) \\cdot \\left(z - 1\\right)",
+    );
+}
+
+#[test]
+fn test_functions_and_params() {
+    check(
+        "2 sin(z) + 3a",
+        "2sin(z) + 3a",
+        "2 \\sin\\left(z\\right) + 3 a",
+    );
+    check("4 i z^2", "4i * z^2", "4 i \\cdot {z}^{2}");
+    check("log(z, 2)", "log(z, 2)", "\\log_{2}\\left(z\\right)");
+    check(
+        "sqrt(abs(z)) / conj(z)",
+        "sqrt(abs(z)) / conj(z)",
+        "\\frac{\\sqrt{\\left|z\\right|}}{\\overline{z}}",
+    );
+    check(
+        "gamma(z) zeta(z)",
+        "gamma(z) * zeta(z)",
+        "\\Gamma\\left(z\\right) \\cdot \\zeta\\left(z\\right)",
+    );
+    check(
+        "pow(z + 1, 2) omega",
+        "pow(z + 1, 2) * omega",
+        "{\\left(z + 1\\right)}^{2} \\cdot \\mathit{omega}",
+    );
+}
+
+#[test]
+fn test_constants() {
+    let constant = |re, im| Node::Const {
+        val: Complex::new(re, im),
+    };
+    assert_eq!(constant(0.5, 0.0).to_infix(), "0.5");
+    assert_eq!(constant(0.0, 1.0).to_infix(), "i");
+    assert_eq!(constant(-3.0, 0.0).to_infix(), "-3");
+    assert_eq!(constant(3.0, -2.0).to_infix(), "(3 - 2i)");
+    assert_eq!(constant(0.0, -1.0).to_infix(), "(-i)");
+    assert_eq!(constant(f64::INFINITY, 0.0).to_latex(), "\\infty");
+
+    // Folded constants must print to expressions with the same value
+    for expr in ["2 - 3i", "-(4i)", "2 * (3 - 5)", "(1 + i)^2"] {
+        let folded = parser::parse(expr).unwrap().simplify();
+        let reparsed = parser::parse(&folded.to_infix()).unwrap();
+        let z = Complex::new(0.0, 0.0);
+        assert_eq!(folded.to_closure()(z), reparsed.to_closure()(z), "{expr}");
+    }
+}
+
+const FUNCTIONS: [Function; 8] = [
+    Function::Sqrt,
+    Function::Exp,
+    Function::Sin,
+    Function::Atanh,
+    Function::Abs,
+    Function::Gamma,
+    Function::Log,
+    Function::Atan2,
+];
+
+/// Trees with the shapes the parser produces
+fn arb_node() -> impl Strategy<Value = Node> {
+    let leaf = prop_oneof![
+        (0u32..1000).prop_map(|n| Node::Const {
+            val: Complex::new(n as f64 / 8.0, 0.0)
+        }),
+        Just(Node::Const {
+            val: Complex::new(0.0, 1.0)
+        }),
+        Just(Node::Var),
+        prop::sample::select(vec!["a", "b2", "omega"]).prop_map(|name| Node::Param {
+            name: name.to_string()
+        }),
+    ];
+    leaf.prop_recursive(6, 48, 2, |inner| {
+        prop_oneof![
+            (
+                prop::sample::select(vec![
+                    Token::Add,
+                    Token::Sub,
+                    Token::Mult,
+                    Token::Div,
+                    Token::Pow
+                ]),
+                inner.clone(),
+                inner.clone()
+            )
+                .prop_map(|(op, left, right)| Node::Binary {
+                    op,
+                    left: Some(Box::new(left)),
+                    right: Some(Box::new(right)),
+                }),
+            inner.clone().prop_map(|child| Node::Unary {
+                op: Token::Sub,
+                child: Some(Box::new(child)),
+            }),
+            (
+                prop::sample::select(FUNCTIONS.to_vec()),
+                inner.clone(),
+                inner
+            )
+                .prop_map(|(fun, a, b)| Node::Fun {
+                    fun,
+                    args: if *fun.arity().end() == 2 {
+                        vec![a, b]
+                    } else {
+                        vec![a]
+                    },
+                }),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn prop_infix_round_trip(node in arb_node()) {
+        let printed = node.to_infix();
+        prop_assert_eq!(parser::parse(&printed).unwrap(), node, "{}", printed);
+    }
+}
@@ -0,0 +1,93 @@
+use native::domain_color::{self, DCOptions, Viewport};
+use native::renderer::Renderer;
+
+const FUN: &str = "(z^2 - 1) / (z - 2i)";
+
+fn options(viewport: Viewport) -> DCOptions {
+    DCOptions {
+        viewport,
+        preserve_aspect: false,
+        phase_contours: 8,
+        ..Default::default()
+    }
+}
+
+/// Largest difference between two images' channels. Panning moves the
+/// viewport by multiples of the pixel size, so the sample points of a panned
+/// image and a fresh render can differ in the last bits.
+fn max_difference(a: &[u8], b: &[u8]) -> u8 {
+    a.iter().zip(b).map(|(a, b)| a.abs_diff(*b)).max().unwrap()
+}
+
+#[test]
+fn test_pan_matches_full_render() {
+    let (width, height) = (37, 23);
+    let viewport = Viewport::new(-2.0, 1.7, -1.0, 1.3);
+    let mut renderer = Renderer::new(width, height, FUN, &options(viewport)).unwrap();
+    assert_eq!(
+        renderer.image(),
+        &domain_color::render(width, height, FUN, &options(viewport)).unwrap()
+    );
+
+    for (dx, dy) in [(5, 0), (0, -3), (-7, 4), (2, 2), (0, 0), (40, 1), (-1, -30)] {
+        let panned = renderer.pan(dx, dy).clone();
+        let fresh =
+            domain_color::render(width, height, FUN, &options(renderer.viewport())).unwrap();
+        assert!(
+            max_difference(&panned.pixels, &fresh.pixels) <= 1,
+            "pan by ({dx}, {dy})"
+        );
+    }
+}
+
+#[test]
+fn test_pan_adaptive() {
+    // The phase only changes along the real axis, so pixels of a strip exposed
+    // by a horizontal pan only refine by comparing with the neighbors outside
+    // of it. The viewport and pan are exact in binary, so the images match.
+    let fun = "exp(4i (z + conj(z)))";
+    let (width, height) = (32, 16);
+    let options = |viewport| DCOptions {
+        supersampling: 4,
+        adaptive: true,
+        ..options(viewport)
+    };
+    let viewport = Viewport::new(-1.875, 0.125, -0.5, 0.5);
+    let mut renderer = Renderer::new(width, height, fun, &options(viewport)).unwrap();
+    for (dx, dy) in [(-1, 0), (0, 1), (-2, -1)] {
+        let panned = renderer.pan(dx, dy).clone();
+        let fresh =
+            domain_color::render(width, height, fun, &options(renderer.viewport())).unwrap();
+        assert_eq!(panned, fresh, "pan by ({dx}, {dy})");
+    }
+}
+
+#[test]
+fn test_pan_moves_viewport() {
+    let viewport = Viewport::new(0.0, 10.0, 0.0, 5.0);
+    let mut renderer = Renderer::new(20, 10, "z", &options(viewport)).unwrap();
+    let before = renderer.image().clone();
+    // Dragging right and down shows what was to the left and above
+    renderer.pan(4, 2);
+    assert_eq!(renderer.viewport(), Viewport::new(-2.0, 8.0, 1.0, 6.0));
+    let after = renderer.image();
+    for (x, y) in [(4, 2), (10, 5), (19, 9)] {
+        assert_eq!(after.pixel(x, y), before.pixel(x - 4, y - 2));
+    }
+}
+
+#[test]
+fn test_zoom() {
+    let viewport = Viewport::new(-4.0, 4.0, -2.0, 2.0);
+    let mut renderer = Renderer::new(16, 8, FUN, &options(viewport)).unwrap();
+    // Zooming in about the center of the top left quarter, -2 + i, which stays in place
+    renderer.zoom(2.0, 4.0, 2.0).unwrap();
+    assert_eq!(renderer.viewport(), Viewport::new(-3.0, 1.0, -0.5, 1.5));
+    assert_eq!(
+        renderer.image(),
+        &domain_color::render(16, 8, FUN, &options(renderer.viewport())).unwrap()
+    );
+    assert!(renderer
+        .set_viewport(Viewport::new(1.0, 1.0, 0.0, 1.0))
+        .is_err());
+}
@@ -0,0 +1,150 @@
+use native::parser;
+use num::Complex;
+
+/// Parses `expr` and checks the Mermaid diagrams before and after simplification
+fn check(expr: &str, before: &str, after: &str) {
+    let tree = parser::parse(expr).unwrap();
+    assert_eq!(tree.to_mermaid(), before, "{expr} before simplification");
+    let simplified = tree.clone().simplify();
+    assert_eq!(
+        simplified.to_mermaid(),
+        after,
+        "{expr} after simplification"
+    );
+
+    // Simplification must not change the value
+    let (f, g) = (tree.to_closure(), simplified.to_closure());
+    for z in [Complex::new(0.5, -1.5), Complex::new(-2.0, 0.25)] {
+        assert!((f(z) - g(z)).norm() <= 1e-12 * f(z).norm(), "{expr} at {z}");
+    }
+}
+
+#[test]
+fn test_constant_folding() {
+    check(
+        "2 * 3 z",
+        "flowchart TD
+    0[*]
+    1[*]
+    2[z]
+    3[2+0i]
+    4[3+0i]
+    0 --> 1
+    0 --> 2
+    1 --> 3
+    1 --> 4",
+        "flowchart TD
+    0[*]
+    1[6+0i]
+    2[z]
+    0 --> 1
+    0 --> 2",
+    );
+    check(
+        "z + sqrt(4) i",
+        "flowchart TD
+    0[+]
+    1[z]
+    2[*]
+    3[sqrt]
+    4[0+1i]
+    5[4+0i]
+    0 --> 1
+    0 --> 2
+    2 --> 3
+    2 --> 4
+    3 --> 5",
+        "flowchart TD
+    0[+]
+    1[z]
+    2[0+2i]
+    0 --> 1
+    0 --> 2",
+    );
+}
+
+#[test]
+fn test_identities() {
+    check(
+        "(z * 1 + 0)^1 / 1",
+        "flowchart TD
+    0[/]
+    1[^]
+    2[1+0i]
+    3[+]
+    4[1+0i]
+    5[*]
+    6[0+0i]
+    7[z]
+    8[1+0i]
+    0 --> 1
+    0 --> 2
+    1 --> 3
+    1 --> 4
+    3 --> 5
+    3 --> 6
+    5 --> 7
+    5 --> 8",
+        "flowchart TD
+    0[z]
+    ",
+    );
+}
+
+#[test]
+fn test_negation() {
+    check(
+        "-(-z) + -3",
+        "flowchart TD
+    0[+]
+    1[-]
+    2[-]
+    3[-]
+    4[3+0i]
+    5[z]
+    0 --> 1
+    0 --> 2
+    1 --> 3
+    2 --> 4
+    3 --> 5",
+        "flowchart TD
+    0[-]
+    1[z]
+    2[3+0i]
+    0 --> 1
+    0 --> 2",
+    );
+    check(
+        "(-z) (-sin(z)) - -z",
+        "flowchart TD
+    0[-]
+    1[*]
+    2[-]
+    3[-]
+    4[-]
+    5[z]
+    6[z]
+    7[sin]
+    8[z]
+    0 --> 1
+    0 --> 2
+    1 --> 3
+    1 --> 4
+    2 --> 5
+    3 --> 6
+    4 --> 7
+    7 --> 8",
+        "flowchart TD
+    0[+]
+    1[*]
+    2[z]
+    3[z]
+    4[sin]
+    5[z]
+    0 --> 1
+    0 --> 2
+    1 --> 3
+    1 --> 4
+    4 --> 5",
+    );
+}
@@ -0,0 +1,126 @@
+use native::domain_color::{self, DCOptions, RenderError, Viewport};
+use native::tiles::{self, TileCache, TileKey, TileRenderer, TILE_BYTES, TILE_SIZE};
+use std::sync::Arc;
+
+const FUN: &str = "sin(z) / (z - 1)";
+
+#[test]
+fn test_tile_geometry() {
+    assert_eq!(
+        tiles::tile_viewport(0, 0, 0),
+        Viewport::new(0.0, 1.0, 0.0, 1.0)
+    );
+    assert_eq!(
+        tiles::tile_viewport(2, -3, 1),
+        Viewport::new(-0.75, -0.5, 0.25, 0.5)
+    );
+    assert_eq!(
+        tiles::tile_viewport(-1, 1, -1),
+        Viewport::new(2.0, 4.0, -2.0, 0.0)
+    );
+
+    // Row by row from the top left, including partly covered tiles
+    assert_eq!(
+        tiles::tiles_covering(&Viewport::new(-0.5, 1.0, -1.0, 0.25), 0),
+        [(-1, 0), (0, 0), (-1, -1), (0, -1)]
+    );
+    assert_eq!(
+        tiles::tiles_covering(&Viewport::new(0.0, 1.0, 0.0, 1.0), 1).len(),
+        4
+    );
+
+    // 512 pixels across 4 units need pixels of 1/128, so tiles of side 2
+    let viewport = Viewport::new(-2.0, 2.0, -2.0, 2.0);
+    assert_eq!(tiles::zoom_for(&viewport, 512, 512), -1);
+    assert_eq!(tiles::zoom_for(&viewport, 513, 512), 0);
+
+    // Degenerate images clamp the zoom instead of overflowing
+    assert_eq!(tiles::zoom_for(&viewport, 0, 0), tiles::MIN_ZOOM);
+    let tiny = Viewport::new(0.0, 1e-300, 0.0, 1e-300);
+    assert_eq!(tiles::zoom_for(&tiny, 512, 512), tiles::MAX_ZOOM);
+    assert_eq!(tiles::tile_side(i32::MIN), f64::INFINITY);
+
+    assert_eq!(
+        tiles::tile_count(&Viewport::new(-0.5, 1.0, -1.0, 0.25), 0),
+        Some(4)
+    );
+    assert_eq!(
+        tiles::tile_count(&Viewport::new(-1e300, 1e300, -1.0, 1.0), 40),
+        None
+    );
+}
+
+#[test]
+fn test_tiles_match_render() {
+    let options = DCOptions {
+        modulus_contours: Some(2.0),
+        ..Default::default()
+    };
+    let mut renderer = TileRenderer::new(16 * TILE_BYTES);
+    let viewport = Viewport::new(-1.5, 0.5, 0.0, 0.75);
+    let found = renderer.tiles(FUN, &options, &viewport, 1).unwrap();
+    assert_eq!(found.len(), 4 * 2);
+    for tile in &found {
+        let expected = domain_color::render(
+            TILE_SIZE,
+            TILE_SIZE,
+            FUN,
+            &DCOptions {
+                viewport: tile.viewport,
+                ..options.clone()
+            },
+        )
+        .unwrap();
+        assert_eq!(*tile.image, expected, "{:?}", tile.key);
+    }
+
+    // A second request comes from the cache
+    assert_eq!(renderer.cache().stats(), (0, 8));
+    let again = renderer.tiles(FUN, &options, &viewport, 1).unwrap();
+    assert_eq!(renderer.cache().stats(), (8, 8));
+    for (a, b) in found.iter().zip(&again) {
+        assert!(Arc::ptr_eq(&a.image, &b.image));
+    }
+
+    // Other options are different tiles
+    let other = DCOptions {
+        phase_contours: 3,
+        ..options.clone()
+    };
+    let different = renderer.tiles(FUN, &other, &viewport, 1).unwrap();
+    assert_ne!(different[0].key, found[0].key);
+    assert_eq!(renderer.cache().len(), 16);
+
+    assert!(matches!(
+        renderer.tiles(FUN, &options, &Viewport::new(0.0, 100.0, 0.0, 100.0), 2),
+        Err(RenderError::TooManyTiles { count: 160000 })
+    ));
+    // Rejected before listing the tiles
+    assert!(matches!(
+        renderer.tiles(FUN, &options, &Viewport::new(-1e6, 1e6, -1e6, 1e6), 40),
+        Err(RenderError::TooManyTiles { count: usize::MAX })
+    ));
+}
+
+#[test]
+fn test_cache_eviction() {
+    let image = || Arc::new(native::image::Image::new(TILE_SIZE, TILE_SIZE));
+    let key = |x| TileKey {
+        plot: 0,
+        zoom: 0,
+        x,
+        y: 0,
+    };
+    let mut cache = TileCache::new(2 * TILE_BYTES);
+    cache.insert(key(0), image());
+    cache.insert(key(1), image());
+    // Using tile 0 makes tile 1 the least recently used
+    assert!(cache.get(&key(0)).is_some());
+    cache.insert(key(2), image());
+    assert!(cache.contains(&key(0)) && !cache.contains(&key(1)) && cache.contains(&key(2)));
+    assert_eq!(cache.memory_used(), 2 * TILE_BYTES);
+
+    cache.set_budget(TILE_BYTES);
+    assert_eq!(cache.len(), 1);
+    assert!(cache.contains(&key(2)));
+}
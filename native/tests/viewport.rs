@@ -0,0 +1,51 @@
+use native::viewport::Viewport;
+use num::Complex;
+
+#[test]
+fn test_pixel_mapping() {
+    let viewport = Viewport::new(-3.0, 1.0, 2.0, 4.0);
+    let (width, height) = (200, 50);
+    assert_eq!(
+        viewport.pixel_to_complex(0.0, 0.0, width, height),
+        Complex::new(-3.0, 4.0)
+    );
+    assert_eq!(
+        viewport.pixel_to_complex(200.0, 50.0, width, height),
+        Complex::new(1.0, 2.0)
+    );
+    assert_eq!(
+        viewport.pixel_to_complex(0.5, 49.5, width, height),
+        Complex::new(-2.99, 2.02)
+    );
+    for (x, y) in [(0.0, 0.0), (12.5, 7.25), (199.5, 0.5), (100.0, 25.0)] {
+        let z = viewport.pixel_to_complex(x, y, width, height);
+        let (x2, y2) = viewport.complex_to_pixel(z, width, height);
+        assert!((x - x2).abs() < 1e-9 && (y - y2).abs() < 1e-9, "({x}, {y})");
+    }
+    assert_eq!(viewport.center(), Complex::new(-1.0, 3.0));
+    assert_eq!(viewport.aspect(), 2.0);
+}
+
+#[test]
+fn test_fit_aspect() {
+    let viewport = Viewport::new(-3.0, 1.0, 2.0, 4.0);
+    // Already 2:1
+    assert_eq!(viewport.fit_aspect(200, 100), viewport);
+    // Taller image, the y range grows
+    assert_eq!(
+        viewport.fit_aspect(100, 100),
+        Viewport::new(-3.0, 1.0, 1.0, 5.0)
+    );
+    // Wider image, the x range grows
+    assert_eq!(
+        viewport.fit_aspect(400, 100),
+        Viewport::new(-5.0, 3.0, 2.0, 4.0)
+    );
+}
+
+#[test]
+fn test_centered() {
+    let viewport = Viewport::centered(Complex::new(1.0, -1.0), 0.5, 8, 4);
+    assert_eq!(viewport, Viewport::new(-1.0, 3.0, -2.0, 0.0));
+    assert_eq!(viewport.fit_aspect(8, 4), viewport);
+}